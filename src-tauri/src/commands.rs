@@ -1,20 +1,154 @@
+use crate::hash_cache::{HashCache, HashCacheInfo};
+use crate::options::ScanOptions;
 use crate::scanner;
-use tauri::command;
+use crate::session::ScanSessions;
+use serde::Serialize;
+use std::sync::Mutex;
+use tauri::{async_runtime, command, AppHandle, Emitter, Manager, State};
 
-/// フォルダをスキャンして重複グループを返す
+/// スキャン進捗を通知するイベント名
+const SCAN_PROGRESS_EVENT: &str = "scan-progress";
+/// 確定した重複グループを通知するイベント名
+const SCAN_GROUP_EVENT: &str = "scan-group";
+/// スキャン終了（完了・失敗・キャンセル）を通知するイベント名
+const SCAN_FINISHED_EVENT: &str = "scan-finished";
+
+/// `scan-progress` イベントのペイロード
+#[derive(Clone, Serialize)]
+struct ScanProgressEvent<'a> {
+    scan_id: u64,
+    #[serde(flatten)]
+    progress: &'a scanner::ScanProgress,
+}
+
+/// `scan-group` イベントのペイロード
+#[derive(Clone, Serialize)]
+struct ScanGroupEvent<'a> {
+    scan_id: u64,
+    group: &'a scanner::DuplicateGroup,
+}
+
+/// `scan-finished` イベントのペイロード
+#[derive(Clone, Serialize)]
+struct ScanFinishedEvent {
+    scan_id: u64,
+    report: Option<scanner::ScanReport>,
+    error: Option<String>,
+    cancelled: bool,
+}
+
+/// バックグラウンドでフォルダ（複数可）のスキャンを開始し、スキャンIDを返す
+/// 参照フォルダは比較にのみ使い、このスキャンIDを渡した `delete_files` ではその中のファイルを削除できない
+/// 進捗は `scan-progress`、確定した重複グループは `scan-group`（サイズの大きい順）、
+/// 結果は `scan-finished` イベントで通知する
+#[command]
+pub fn start_scan(
+    app: AppHandle,
+    sessions: State<'_, ScanSessions>,
+    options: ScanOptions,
+) -> Result<u64, String> {
+    options.validate()?;
+    let (scan_id, cancel) = sessions.register(&options.reference_paths);
+
+    let spawned = std::thread::Builder::new()
+        .name(format!("scan-{}", scan_id))
+        .spawn(move || {
+            let on_progress = |progress: &scanner::ScanProgress| {
+                let _ = app.emit(SCAN_PROGRESS_EVENT, ScanProgressEvent { scan_id, progress });
+            };
+            let on_group = |group: &scanner::DuplicateGroup| {
+                let _ = app.emit(SCAN_GROUP_EVENT, ScanGroupEvent { scan_id, group });
+            };
+            let cache = app.state::<Mutex<HashCache>>();
+            let context = scanner::ScanContext {
+                cache: Some(cache.inner()),
+                cancel: &cancel,
+                on_progress: &on_progress,
+                on_group: &on_group,
+            };
+            let result = scanner::scan_for_duplicates(&options, &context);
+            // キャッシュの保存に失敗してもスキャン結果には影響しないため無視する
+            let _ = cache.lock().unwrap().save();
+            app.state::<ScanSessions>().finish(scan_id);
+
+            let event = match result {
+                Ok(report) => ScanFinishedEvent {
+                    scan_id,
+                    report: Some(report),
+                    error: None,
+                    cancelled: false,
+                },
+                Err(error) => ScanFinishedEvent {
+                    scan_id,
+                    report: None,
+                    cancelled: error == scanner::CANCELLED_MESSAGE,
+                    error: Some(error),
+                },
+            };
+            let _ = app.emit(SCAN_FINISHED_EVENT, event);
+        });
+
+    if let Err(e) = spawned {
+        sessions.finish(scan_id);
+        return Err(format!("スキャンを開始できません: {}", e));
+    }
+    Ok(scan_id)
+}
+
+/// 実行中のスキャンを中断する
+#[command]
+pub fn cancel_scan(sessions: State<'_, ScanSessions>, scan_id: u64) -> Result<(), String> {
+    if sessions.cancel(scan_id) {
+        Ok(())
+    } else {
+        Err(format!("スキャンが見つかりません: {}", scan_id))
+    }
+}
+
+/// ファイル操作を伴う処理をブロッキング処理用のスレッドで実行する
+/// 同期コマンドはメインスレッドで実行されるため、時間がかかると画面や他のコマンドが止まる
+async fn run_blocking<T, F>(task: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, String> + Send + 'static,
+{
+    async_runtime::spawn_blocking(task)
+        .await
+        .map_err(|e| format!("処理を実行できません: {}", e))?
+}
+
+/// ハッシュキャッシュの件数とファイルサイズを取得（未読み込みならキャッシュファイルを読む）
+#[command]
+pub async fn get_hash_cache_info(app: AppHandle) -> Result<HashCacheInfo, String> {
+    run_blocking(move || Ok(app.state::<Mutex<HashCache>>().lock().unwrap().info())).await
+}
+
+/// ハッシュキャッシュを削除
 #[command]
-pub fn scan_folder(path: String, mode: String, recursive: bool) -> Result<Vec<scanner::DuplicateGroup>, String> {
-    scanner::scan_for_duplicates(&path, &mode, recursive)
+pub async fn clear_hash_cache(app: AppHandle) -> Result<(), String> {
+    run_blocking(move || app.state::<Mutex<HashCache>>().lock().unwrap().clear()).await
 }
 
 /// ファイルのプレビューを取得
 #[command]
-pub fn get_file_preview(path: String) -> Result<scanner::FilePreview, String> {
-    scanner::get_preview(&path)
+pub async fn get_file_preview(path: String) -> Result<scanner::FilePreview, String> {
+    run_blocking(move || scanner::get_preview(&path)).await
 }
 
-/// 選択されたファイルをゴミ箱に移動
+/// 選択されたファイルをゴミ箱に移動（`scan_id` のスキャンの参照フォルダ内のファイルは拒否する）
+/// 大量のファイルでも他のコマンド（プレビューなど）を止めないよう、別スレッドで実行する
 #[command]
-pub fn delete_files(paths: Vec<String>) -> Result<scanner::DeleteResult, String> {
-    scanner::delete_files_to_trash(&paths)
+pub async fn delete_files(
+    app: AppHandle,
+    scan_id: u64,
+    paths: Vec<String>,
+) -> Result<scanner::DeleteResult, String> {
+    run_blocking(move || {
+        let protected_roots = app
+            .state::<ScanSessions>()
+            .reference_roots(scan_id)
+            .ok_or_else(|| format!("スキャン結果が見つかりません: {}", scan_id))?;
+        scanner::delete_files_to_trash(&paths, &protected_roots)
+    })
+    .await
 }
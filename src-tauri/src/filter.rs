@@ -0,0 +1,263 @@
+use crate::options::ScanOptions;
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use std::env;
+use std::path::Path;
+
+/// 既定で除外するキャッシュ・ゴミ箱・仮想ファイルシステムのフォルダ（"~" はホームフォルダ）
+/// ゴミ箱の中身は削除済みのファイルなので、重複として報告しても整理の役に立たない
+#[cfg(target_os = "linux")]
+pub const DEFAULT_SYSTEM_EXCLUDES: &[&str] = &[
+    "~/.cache",
+    "~/.local/share/Trash",
+    "~/.var/app/*/cache",
+    "/**/.Trash-*",
+    "/proc",
+    "/sys",
+    "/dev",
+    "/run",
+];
+
+#[cfg(not(target_os = "linux"))]
+pub const DEFAULT_SYSTEM_EXCLUDES: &[&str] = &[];
+
+/// 走査時に適用するパターン
+/// "/" を含まないパターンは名前に、含むパターンはスキャン対象フォルダからの相対パスに対して照合する
+struct PatternSet {
+    names: GlobSet,
+    paths: GlobSet,
+    is_empty: bool,
+}
+
+impl PatternSet {
+    fn new(patterns: &[String]) -> Result<Self, String> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for pattern in patterns {
+            let pattern = pattern.trim().trim_end_matches('/');
+            if pattern.is_empty() {
+                continue;
+            }
+            let invalid = |e: globset::Error| format!("不正なパターンです: {} ({})", pattern, e);
+            if pattern.contains('/') {
+                // "/" を越えて "*" が一致しないようにする（"**" は越えられる）
+                let glob = GlobBuilder::new(pattern.trim_start_matches('/'))
+                    .literal_separator(true)
+                    .build()
+                    .map_err(invalid)?;
+                paths.add(glob);
+            } else {
+                names.add(Glob::new(pattern).map_err(invalid)?);
+            }
+        }
+        let build = |builder: GlobSetBuilder| {
+            builder
+                .build()
+                .map_err(|e| format!("パターンを構築できません: {}", e))
+        };
+        Ok(Self {
+            names: build(names)?,
+            paths: build(paths)?,
+            is_empty: patterns.iter().all(|p| p.trim().is_empty()),
+        })
+    }
+
+    fn is_match(&self, name: &str, relative: &Path) -> bool {
+        self.names.is_match(name) || self.paths.is_match(relative)
+    }
+}
+
+/// 対象に含める・除外するファイルとフォルダの条件
+pub struct ScanFilter {
+    include: PatternSet,
+    exclude: PatternSet,
+    /// 小文字・先頭の "." なし
+    extensions: Vec<String>,
+    excluded_extensions: Vec<String>,
+    /// 絶対パスに対して照合する、既定の除外フォルダ
+    system_excludes: GlobSet,
+    skip_hidden: bool,
+}
+
+impl ScanFilter {
+    pub fn from_options(options: &ScanOptions) -> Result<Self, String> {
+        Ok(Self {
+            include: PatternSet::new(&options.include_globs)?,
+            exclude: PatternSet::new(&options.exclude_globs)?,
+            extensions: normalize_extensions(&options.extensions),
+            excluded_extensions: normalize_extensions(&options.excluded_extensions),
+            system_excludes: match &options.system_excludes {
+                Some(patterns) => build_system_excludes(patterns)?,
+                None => build_system_excludes(DEFAULT_SYSTEM_EXCLUDES)?,
+            },
+            skip_hidden: options.skip_hidden,
+        })
+    }
+
+    /// フォルダに入るかどうか（除外パターンに一致したフォルダは中を走査しない）
+    pub fn allows_dir(&self, path: &Path, root: &Path) -> bool {
+        let name = file_name(path);
+        if self.skip_hidden && is_hidden(path, &name) {
+            return false;
+        }
+        !self.system_excludes.is_match(path)
+            && !self.exclude.is_match(&name, relative_to(path, root))
+    }
+
+    /// ファイルを対象に含めるかどうか
+    pub fn allows_file(&self, path: &Path, root: &Path) -> bool {
+        let name = file_name(path);
+        let relative = relative_to(path, root);
+        if self.skip_hidden && is_hidden(path, &name) {
+            return false;
+        }
+        if self.exclude.is_match(&name, relative) {
+            return false;
+        }
+        if !self.include.is_empty && !self.include.is_match(&name, relative) {
+            return false;
+        }
+
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if !self.extensions.is_empty() && !self.extensions.contains(&extension) {
+            return false;
+        }
+        !self.excluded_extensions.contains(&extension)
+    }
+}
+
+/// 既定の除外フォルダのパターンを構築する（先頭の "~" はホームフォルダに置き換える）
+fn build_system_excludes(patterns: &[impl AsRef<str>]) -> Result<GlobSet, String> {
+    let home = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| globset::escape(&home.to_string_lossy()));
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.as_ref().trim().trim_end_matches('/');
+        let pattern = match (pattern.strip_prefix('~'), &home) {
+            (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+                format!("{}{}", home, rest)
+            }
+            // ホームフォルダが分からない場合、"~" で始まるパターンは使わない
+            (Some(_), None) => continue,
+            _ => pattern.to_string(),
+        };
+        if pattern.is_empty() {
+            continue;
+        }
+        let glob = GlobBuilder::new(&pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| format!("不正なパターンです: {} ({})", pattern, e))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| format!("パターンを構築できません: {}", e))
+}
+
+/// 隠しファイル・フォルダかどうか（"." で始まる名前、Windows では隠し属性）
+fn is_hidden(path: &Path, name: &str) -> bool {
+    name.starts_with('.') || has_hidden_attribute(path)
+}
+
+#[cfg(windows)]
+fn has_hidden_attribute(path: &Path) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    std::fs::symlink_metadata(path).is_ok_and(|m| m.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+}
+
+#[cfg(not(windows))]
+fn has_hidden_attribute(_path: &Path) -> bool {
+    false
+}
+
+fn normalize_extensions(extensions: &[String]) -> Vec<String> {
+    extensions
+        .iter()
+        .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+        .filter(|e| !e.is_empty())
+        .collect()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+fn relative_to<'a>(path: &'a Path, root: &Path) -> &'a Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(options: ScanOptions) -> ScanFilter {
+        ScanFilter::from_options(&options).unwrap()
+    }
+
+    #[test]
+    fn test_globs_and_extensions() {
+        let root = Path::new("/home/user");
+        let f = filter(ScanOptions {
+            exclude_globs: vec![
+                "node_modules".to_string(),
+                "Thumbs.db".to_string(),
+                "projects/*/target".to_string(),
+            ],
+            excluded_extensions: vec![".TMP".to_string()],
+            ..Default::default()
+        });
+        assert!(!f.allows_dir(Path::new("/home/user/app/node_modules"), root));
+        assert!(!f.allows_dir(Path::new("/home/user/projects/foo/target"), root));
+        assert!(f.allows_dir(Path::new("/home/user/projects/foo/src/target"), root));
+        assert!(!f.allows_file(Path::new("/home/user/pics/Thumbs.db"), root));
+        assert!(!f.allows_file(Path::new("/home/user/a.tmp"), root));
+        assert!(f.allows_file(Path::new("/home/user/a.jpg"), root));
+
+        let f = filter(ScanOptions {
+            include_globs: vec!["IMG_*".to_string()],
+            extensions: vec!["jpg".to_string()],
+            ..Default::default()
+        });
+        assert!(f.allows_file(Path::new("/home/user/IMG_001.JPG"), root));
+        assert!(!f.allows_file(Path::new("/home/user/IMG_001.png"), root));
+        assert!(!f.allows_file(Path::new("/home/user/photo.jpg"), root));
+
+        let invalid = ScanOptions {
+            exclude_globs: vec!["[".to_string()],
+            ..Default::default()
+        };
+        assert!(ScanFilter::from_options(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_hidden_and_system_excludes() {
+        let root = Path::new("/home/user");
+        let f = filter(ScanOptions {
+            skip_hidden: true,
+            system_excludes: Some(vec!["/**/.Trash-*".to_string(), "/srv/cache".to_string()]),
+            ..Default::default()
+        });
+        assert!(!f.allows_dir(Path::new("/home/user/.config"), root));
+        assert!(!f.allows_file(Path::new("/home/user/docs/.env"), root));
+        assert!(f.allows_file(Path::new("/home/user/docs/a.env"), root));
+        assert!(!f.allows_dir(Path::new("/media/usb/.Trash-1000"), root));
+        assert!(!f.allows_dir(Path::new("/srv/cache"), root));
+        assert!(f.allows_dir(Path::new("/srv/cache2"), root));
+
+        // 既定の除外を無効にすれば隠しフォルダ以外は走査する
+        let f = filter(ScanOptions {
+            system_excludes: Some(Vec::new()),
+            ..Default::default()
+        });
+        assert!(f.allows_dir(Path::new("/home/user/.config"), root));
+        assert!(f.allows_dir(Path::new("/media/usb/.Trash-1000"), root));
+    }
+}
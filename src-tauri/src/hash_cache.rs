@@ -0,0 +1,416 @@
+use crate::hasher::{Digest, HashAlgorithm, DIGEST_LEN};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// キャッシュファイルの先頭に置く識別子（形式を変えたら末尾の番号を上げる）
+const CACHE_MAGIC: &[u8; 8] = b"FDOHASH4";
+
+/// 読み込むパスの長さの上限（壊れたファイルの長さで巨大な領域を確保しないため）
+const MAX_PATH_LEN: usize = 64 * 1024;
+
+/// この日数のあいだ参照されなかったエントリは保存時に破棄する
+/// （削除・移動されたファイルのエントリが際限なく残らないようにする）
+const MAX_UNUSED_DAYS: u64 = 90;
+
+/// キャッシュファイル名（アプリのデータディレクトリ直下に置く）
+pub const CACHE_FILE_NAME: &str = "hash_cache.bin";
+
+/// ハッシュの再利用可否を判定するためのファイルのメタデータ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileStamp {
+    pub size: u64,
+    pub mtime_secs: i64,
+    pub mtime_nanos: u32,
+    pub dev: u64,
+    pub ino: u64,
+}
+
+impl FileStamp {
+    /// 更新日時が取得できない場合は None（キャッシュ対象外）
+    pub fn from_metadata(metadata: &fs::Metadata) -> Option<Self> {
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        let (dev, ino) = device_and_inode(metadata);
+        Some(Self {
+            size: metadata.len(),
+            mtime_secs: mtime.as_secs() as i64,
+            mtime_nanos: mtime.subsec_nanos(),
+            dev,
+            ino,
+        })
+    }
+}
+
+/// ファイルの (デバイス, inode)
+#[cfg(unix)]
+pub fn device_and_inode(metadata: &fs::Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+/// Windows では安定版 std から取得できないため (0, 0) を返す（キャッシュはサイズと更新日時のみで判定する）
+#[cfg(not(unix))]
+pub fn device_and_inode(_metadata: &fs::Metadata) -> (u64, u64) {
+    (0, 0)
+}
+
+/// ファイルを指すハードリンクの数
+#[cfg(unix)]
+pub fn link_count(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink()
+}
+
+/// inode を取得できない環境ではハードリンクを区別しないため 1 とみなす
+#[cfg(not(unix))]
+pub fn link_count(_metadata: &fs::Metadata) -> u64 {
+    1
+}
+
+struct CacheEntry {
+    stamp: FileStamp,
+    algorithm: HashAlgorithm,
+    digest: Digest,
+    /// 最後に参照・登録した日（UNIX エポックからの日数）
+    last_used: u64,
+}
+
+/// ハッシュキャッシュの状態（コマンドからの問い合わせ用）
+#[derive(Debug, Clone, Serialize)]
+pub struct HashCacheInfo {
+    pub path: String,
+    pub entries: usize,
+    pub file_size: u64,
+}
+
+/// パス・サイズ・更新日時・inode をキーにした、ディスク上のハッシュキャッシュ
+/// ハッシュの計算方法も記録し、異なる方法で計算したハッシュを比較しないようにする
+/// 起動を遅くしないよう、ファイルは初めて参照されたときに読み込む
+pub struct HashCache {
+    path: PathBuf,
+    entries: Option<HashMap<String, CacheEntry>>,
+    dirty: bool,
+}
+
+impl HashCache {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            entries: None,
+            dirty: false,
+        }
+    }
+
+    /// 未読み込みなら読み込む（壊れている・存在しない場合は空のキャッシュとして扱う）
+    fn entries(&mut self) -> &mut HashMap<String, CacheEntry> {
+        let path = &self.path;
+        self.entries
+            .get_or_insert_with(|| read_cache_file(path).unwrap_or_default())
+    }
+
+    /// メタデータと計算方法が一致する場合のみキャッシュ済みのハッシュを返す
+    /// メタデータが一致しないエントリはファイルが変更されたものとみなして破棄する
+    pub fn get(
+        &mut self,
+        file_path: &Path,
+        stamp: &FileStamp,
+        algorithm: HashAlgorithm,
+    ) -> Option<Digest> {
+        let key = file_path.to_string_lossy();
+        let today = today();
+        let entries = self.entries();
+        match entries.get_mut(key.as_ref()) {
+            Some(entry) if entry.stamp == *stamp => {
+                if entry.algorithm != algorithm {
+                    return None;
+                }
+                let digest = entry.digest;
+                if entry.last_used != today {
+                    entry.last_used = today;
+                    self.dirty = true;
+                }
+                Some(digest)
+            }
+            Some(_) => {
+                entries.remove(key.as_ref());
+                self.dirty = true;
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// 1ファイルにつき1つの計算方法のハッシュだけを保持する
+    pub fn insert(
+        &mut self,
+        file_path: &Path,
+        stamp: FileStamp,
+        algorithm: HashAlgorithm,
+        digest: Digest,
+    ) {
+        let key = file_path.to_string_lossy().to_string();
+        self.entries().insert(
+            key,
+            CacheEntry {
+                stamp,
+                algorithm,
+                digest,
+                last_used: today(),
+            },
+        );
+        self.dirty = true;
+    }
+
+    /// 変更があればディスクへ書き出す（一時ファイルに書いてから置き換える）
+    /// 長期間参照されていないエントリはこのときに破棄する
+    pub fn save(&mut self) -> Result<(), String> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let Some(entries) = &mut self.entries else {
+            return Ok(());
+        };
+        let today = today();
+        entries.retain(|_, entry| today.saturating_sub(entry.last_used) <= MAX_UNUSED_DAYS);
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("キャッシュフォルダを作成できません: {}", e))?;
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        write_cache_file(&tmp_path, entries)
+            .map_err(|e| format!("キャッシュの書き込みに失敗: {}", e))?;
+        fs::rename(&tmp_path, &self.path)
+            .map_err(|e| format!("キャッシュの書き込みに失敗: {}", e))?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// キャッシュを空にし、ファイルも削除する
+    pub fn clear(&mut self) -> Result<(), String> {
+        self.entries = Some(HashMap::new());
+        self.dirty = false;
+        match fs::remove_file(&self.path) {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!("キャッシュの削除に失敗: {}", e)),
+        }
+    }
+
+    pub fn info(&mut self) -> HashCacheInfo {
+        let entries = self.entries().len();
+        HashCacheInfo {
+            path: self.path.to_string_lossy().to_string(),
+            entries,
+            file_size: fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0),
+        }
+    }
+}
+
+fn read_cache_file(path: &Path) -> std::io::Result<HashMap<String, CacheEntry>> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != CACHE_MAGIC {
+        return Err(invalid_data("unknown cache format"));
+    }
+
+    let count = read_u64(&mut reader)?;
+    let mut entries = HashMap::new();
+    for _ in 0..count {
+        let path_len = read_u32(&mut reader)? as usize;
+        if path_len > MAX_PATH_LEN {
+            return Err(invalid_data("path too long"));
+        }
+        let file_path = read_string(&mut reader, path_len)?;
+        let stamp = FileStamp {
+            size: read_u64(&mut reader)?,
+            mtime_secs: read_u64(&mut reader)? as i64,
+            mtime_nanos: read_u32(&mut reader)?,
+            dev: read_u64(&mut reader)?,
+            ino: read_u64(&mut reader)?,
+        };
+        let algorithm = HashAlgorithm::from_id(read_u8(&mut reader)?)
+            .ok_or_else(|| invalid_data("unknown hash algorithm"))?;
+        let mut digest = [0u8; DIGEST_LEN];
+        reader.read_exact(&mut digest)?;
+        let last_used = read_u64(&mut reader)?;
+        entries.insert(
+            file_path,
+            CacheEntry {
+                stamp,
+                algorithm,
+                digest,
+                last_used,
+            },
+        );
+    }
+    Ok(entries)
+}
+
+fn write_cache_file(path: &Path, entries: &HashMap<String, CacheEntry>) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    writer.write_all(CACHE_MAGIC)?;
+    writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+    for (file_path, entry) in entries {
+        writer.write_all(&(file_path.len() as u32).to_le_bytes())?;
+        writer.write_all(file_path.as_bytes())?;
+        writer.write_all(&entry.stamp.size.to_le_bytes())?;
+        writer.write_all(&entry.stamp.mtime_secs.to_le_bytes())?;
+        writer.write_all(&entry.stamp.mtime_nanos.to_le_bytes())?;
+        writer.write_all(&entry.stamp.dev.to_le_bytes())?;
+        writer.write_all(&entry.stamp.ino.to_le_bytes())?;
+        writer.write_all(&[entry.algorithm.id()])?;
+        writer.write_all(&entry.digest)?;
+        writer.write_all(&entry.last_used.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+/// 今日の日付（UNIX エポックからの日数）
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86_400)
+        .unwrap_or(0)
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+fn read_u8(reader: &mut impl Read) -> std::io::Result<u8> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> std::io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_string(reader: &mut impl Read, len: usize) -> std::io::Result<String> {
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| invalid_data(&e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stamp(size: u64, mtime_secs: i64) -> FileStamp {
+        FileStamp {
+            size,
+            mtime_secs,
+            mtime_nanos: 0,
+            dev: 1,
+            ino: 42,
+        }
+    }
+
+    #[test]
+    fn test_cache_round_trip_and_invalidation() {
+        let test_dir = "test_hash_cache_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        let cache_path = PathBuf::from(test_dir).join(CACHE_FILE_NAME);
+        let file_path = Path::new("/data/photo.jpg");
+
+        let mut cache = HashCache::new(cache_path.clone());
+        cache.insert(
+            file_path,
+            stamp(100, 1000),
+            HashAlgorithm::Blake3,
+            [1; DIGEST_LEN],
+        );
+        cache.save().unwrap();
+
+        // 読み直しても同じメタデータと計算方法ならヒットする
+        let mut reloaded = HashCache::new(cache_path.clone());
+        assert_eq!(
+            reloaded.get(file_path, &stamp(100, 1000), HashAlgorithm::Blake3),
+            Some([1; DIGEST_LEN])
+        );
+        // 別の方法で計算したハッシュとは比較しない
+        assert_eq!(
+            reloaded.get(file_path, &stamp(100, 1000), HashAlgorithm::Sha256),
+            None
+        );
+        // 更新日時が変わったら無効化される
+        assert_eq!(
+            reloaded.get(file_path, &stamp(100, 2000), HashAlgorithm::Blake3),
+            None
+        );
+        assert_eq!(
+            reloaded.get(file_path, &stamp(100, 1000), HashAlgorithm::Blake3),
+            None
+        );
+
+        reloaded.clear().unwrap();
+        let exists = cache_path.exists();
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        assert!(!exists);
+    }
+
+    #[test]
+    fn test_corrupt_lengths_and_unused_entries() {
+        let test_dir = "test_hash_cache_prune_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir_all(test_dir).unwrap();
+        let cache_path = PathBuf::from(test_dir).join(CACHE_FILE_NAME);
+
+        // 壊れた長さを読んでも巨大な領域は確保せず、空のキャッシュとして扱う
+        let mut corrupt = CACHE_MAGIC.to_vec();
+        corrupt.extend_from_slice(&1u64.to_le_bytes());
+        corrupt.extend_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&cache_path, corrupt).unwrap();
+        let mut cache = HashCache::new(cache_path.clone());
+        let corrupt_entries = cache.info().entries;
+
+        // 長期間参照されていないエントリは保存時に破棄される
+        cache.insert(
+            Path::new("/data/old.jpg"),
+            stamp(100, 1000),
+            HashAlgorithm::Sha256,
+            [2; DIGEST_LEN],
+        );
+        cache.insert(
+            Path::new("/data/new.jpg"),
+            stamp(100, 1000),
+            HashAlgorithm::Sha256,
+            [3; DIGEST_LEN],
+        );
+        cache.entries().get_mut("/data/old.jpg").unwrap().last_used -= MAX_UNUSED_DAYS + 1;
+        cache.save().unwrap();
+        let mut reloaded = HashCache::new(cache_path);
+        let old = reloaded.get(
+            Path::new("/data/old.jpg"),
+            &stamp(100, 1000),
+            HashAlgorithm::Sha256,
+        );
+        let new = reloaded.get(
+            Path::new("/data/new.jpg"),
+            &stamp(100, 1000),
+            HashAlgorithm::Sha256,
+        );
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        assert_eq!(corrupt_entries, 0);
+        assert_eq!(old, None);
+        assert_eq!(new, Some([3; DIGEST_LEN]));
+    }
+}
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use xxhash_rust::xxh3::Xxh3;
+
+/// ハッシュ値の最大のバイト数
+pub const DIGEST_LEN: usize = 32;
+
+/// 固定長のハッシュ値（16進数の文字列にするのは報告するグループだけにし、ファイルごとの領域を抑える）
+/// SHA-256・BLAKE3 は全体を使い、xxh3 は128ビットを先頭の16バイトに入れる
+pub type Digest = [u8; DIGEST_LEN];
+
+/// ファイル内容のハッシュの計算方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    /// SHA-256 と同等の衝突耐性を持ち、高速なディスクでは数倍速い
+    Blake3,
+    /// 非暗号学的ハッシュ（最速だが衝突耐性がないため、部分ハッシュによる絞り込みにのみ使う）
+    Xxh3,
+}
+
+impl HashAlgorithm {
+    /// 計算を始める
+    pub fn hasher(self) -> Box<dyn ContentHasher> {
+        match self {
+            Self::Sha256 => Box::new(Sha256::new()),
+            Self::Blake3 => Box::new(blake3::Hasher::new()),
+            Self::Xxh3 => Box::new(Xxh3::new()),
+        }
+    }
+
+    /// ハッシュが一致すれば内容も同一とみなしてよいか
+    pub fn is_cryptographic(self) -> bool {
+        self != Self::Xxh3
+    }
+
+    /// ハッシュ値のうち実際に使うバイト数
+    pub fn digest_len(self) -> usize {
+        match self {
+            Self::Sha256 | Self::Blake3 => DIGEST_LEN,
+            Self::Xxh3 => 16,
+        }
+    }
+
+    /// ハッシュ値を16進数の文字列にする
+    pub fn to_hex(self, digest: &Digest) -> String {
+        digest[..self.digest_len()]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// キャッシュファイルに保存する番号（変更しないこと）
+    pub fn id(self) -> u8 {
+        match self {
+            Self::Sha256 => 0,
+            Self::Blake3 => 1,
+            Self::Xxh3 => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::Sha256),
+            1 => Some(Self::Blake3),
+            2 => Some(Self::Xxh3),
+            _ => None,
+        }
+    }
+}
+
+/// データを順に入力してハッシュを求める
+pub trait ContentHasher {
+    fn update(&mut self, data: &[u8]);
+
+    fn finish(self: Box<Self>) -> Digest;
+}
+
+impl ContentHasher for Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        sha2::Digest::update(self, data);
+    }
+
+    fn finish(self: Box<Self>) -> Digest {
+        self.finalize().into()
+    }
+}
+
+impl ContentHasher for blake3::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        blake3::Hasher::update(self, data);
+    }
+
+    fn finish(self: Box<Self>) -> Digest {
+        self.finalize().into()
+    }
+}
+
+impl ContentHasher for Xxh3 {
+    fn update(&mut self, data: &[u8]) {
+        Xxh3::update(self, data);
+    }
+
+    fn finish(self: Box<Self>) -> Digest {
+        let mut digest = [0u8; DIGEST_LEN];
+        digest[..16].copy_from_slice(&self.digest128().to_be_bytes());
+        digest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(algorithm: HashAlgorithm, chunks: &[&[u8]]) -> String {
+        let mut hasher = algorithm.hasher();
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        algorithm.to_hex(&hasher.finish())
+    }
+
+    #[test]
+    fn test_algorithms() {
+        assert_eq!(
+            digest(HashAlgorithm::Sha256, &[b"ab", b"c"]),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            digest(HashAlgorithm::Blake3, &[b"ab", b"c"]),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        // 分割して入力しても結果は変わらない
+        assert_eq!(
+            digest(HashAlgorithm::Xxh3, &[b"ab", b"c"]),
+            digest(HashAlgorithm::Xxh3, &[b"abc"])
+        );
+        assert_eq!(digest(HashAlgorithm::Xxh3, &[b"abc"]).len(), 32);
+
+        for algorithm in [
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Blake3,
+            HashAlgorithm::Xxh3,
+        ] {
+            assert_eq!(HashAlgorithm::from_id(algorithm.id()), Some(algorithm));
+        }
+    }
+}
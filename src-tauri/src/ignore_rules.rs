@@ -0,0 +1,137 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::path::Path;
+use std::sync::Arc;
+
+/// 読み込む無視ファイル（優先度の低い順）
+/// `.dupignore` はこのアプリ専用で、Git の管理とは無関係に除外したいものを書く
+pub const IGNORE_FILE_NAMES: &[&str] = &[GITIGNORE_FILE_NAME, ".ignore", ".dupignore"];
+
+/// Git リポジトリ内でのみ適用する無視ファイル
+const GITIGNORE_FILE_NAME: &str = ".gitignore";
+
+/// 走査中のフォルダに適用される無視ルール
+/// 上位フォルダのルールを引き継ぎ、下位フォルダのルールほど優先する（ripgrep と同じ考え方）
+#[derive(Clone, Default)]
+pub struct IgnoreRules {
+    /// 浅いフォルダから順。同じフォルダ内では `IGNORE_FILE_NAMES` の順
+    matchers: Vec<Arc<Gitignore>>,
+    /// Git リポジトリ内か（ripgrep と同じく、.gitignore はリポジトリ内でのみ適用する）
+    in_git_repo: bool,
+}
+
+impl IgnoreRules {
+    /// スキャン対象フォルダより上位にある無視ファイルを読み込む
+    /// （リポジトリの一部だけをスキャンしても、リポジトリ直下の .gitignore が効くようにする）
+    /// .gitignore はリポジトリのルート（.git のあるフォルダ）から下のものだけを読み込み、
+    /// ホームフォルダなどリポジトリ外の .gitignore は適用しない
+    pub fn for_ancestors_of(root: &Path) -> Self {
+        let mut ancestors: Vec<&Path> = root.ancestors().skip(1).collect();
+        ancestors.reverse();
+        ancestors
+            .into_iter()
+            .fold(Self::default(), |rules, dir| rules.enter(dir))
+    }
+
+    /// フォルダに入るときに呼び、そのフォルダの無視ファイルを加えたルールを返す
+    pub fn enter(&self, dir: &Path) -> Self {
+        let mut rules = self.clone();
+        rules.in_git_repo |= dir.join(".git").exists();
+        for file_name in IGNORE_FILE_NAMES {
+            if *file_name == GITIGNORE_FILE_NAME && !rules.in_git_repo {
+                continue;
+            }
+            let ignore_file = dir.join(file_name);
+            if !ignore_file.is_file() {
+                continue;
+            }
+            // 一部の行が不正でも、読み込めた行のルールは適用する
+            let mut builder = GitignoreBuilder::new(dir);
+            let _ = builder.add(&ignore_file);
+            if let Ok(matcher) = builder.build() {
+                if !matcher.is_empty() {
+                    rules.matchers.push(Arc::new(matcher));
+                }
+            }
+        }
+        rules
+    }
+
+    /// 無視するかどうか（"!" で明示的に除外が取り消された場合は無視しない）
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for matcher in self.matchers.iter().rev() {
+            match matcher.matched(path, is_dir) {
+                Match::None => continue,
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_nested_ignore_files() {
+        let test_dir = "test_ignore_rules_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        let root = Path::new(test_dir);
+        fs::create_dir_all(root.join("app/vendor")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join("app/.ignore"), "vendor/\n!keep.log\n").unwrap();
+        fs::write(root.join("app/.dupignore"), "*.bak\n").unwrap();
+
+        let rules = IgnoreRules::default().enter(root);
+        let app_rules = rules.enter(&root.join("app"));
+        let results = [
+            rules.is_ignored(&root.join("target"), true),
+            rules.is_ignored(&root.join("target"), false),
+            rules.is_ignored(&root.join("debug.log"), false),
+            app_rules.is_ignored(&root.join("app/vendor"), true),
+            app_rules.is_ignored(&root.join("app/keep.log"), false),
+            app_rules.is_ignored(&root.join("app/other.log"), false),
+            app_rules.is_ignored(&root.join("app/old.bak"), false),
+            rules.is_ignored(&root.join("old.bak"), false),
+        ];
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        // 下位フォルダのルールが上位のルールを上書きし、".dupignore" も適用される
+        assert_eq!(results, [true, false, true, true, false, true, true, false]);
+    }
+
+    #[test]
+    fn test_gitignore_applies_only_inside_repository() {
+        let test_dir = "test_ignore_rules_repo_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        let home = Path::new(test_dir);
+        let repo = home.join("repo");
+        fs::create_dir_all(home.join("Pictures")).unwrap();
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        // ドットファイル管理用の "*" はリポジトリ外には適用しない
+        fs::write(home.join(".gitignore"), "*\n").unwrap();
+        fs::write(home.join(".dupignore"), "*.tmp\n").unwrap();
+        fs::write(repo.join(".gitignore"), "*.log\n").unwrap();
+
+        let home_rules = IgnoreRules::default().enter(home);
+        let repo_rules = home_rules.enter(&repo);
+        let results = [
+            home_rules.is_ignored(&home.join("Pictures"), true),
+            home_rules.is_ignored(&home.join("photo.jpg"), false),
+            home_rules.is_ignored(&home.join("cache.tmp"), false),
+            repo_rules.is_ignored(&repo.join("photo.jpg"), false),
+            repo_rules.is_ignored(&repo.join("debug.log"), false),
+            repo_rules.is_ignored(&repo.join("cache.tmp"), false),
+        ];
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        // .dupignore はリポジトリの内外を問わず適用する
+        assert_eq!(results, [false, false, true, false, true, true]);
+    }
+}
@@ -1,5 +1,17 @@
 mod commands;
+mod filter;
+mod hash_cache;
+mod hasher;
+mod ignore_rules;
+mod mounts;
+mod name_match;
+mod options;
+mod path_store;
 mod scanner;
+mod session;
+
+use std::sync::Mutex;
+use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -7,8 +19,18 @@ pub fn run() {
         .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
+        // スキャンのスレッドやブロッキング処理用のスレッドから参照するため、状態はすべてスレッド間で共有できる型にする
+        .manage(session::ScanSessions::default())
+        .setup(|app| {
+            let cache_path = app.path().app_data_dir()?.join(hash_cache::CACHE_FILE_NAME);
+            app.manage(Mutex::new(hash_cache::HashCache::new(cache_path)));
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
-            commands::scan_folder,
+            commands::start_scan,
+            commands::cancel_scan,
+            commands::get_hash_cache_info,
+            commands::clear_hash_cache,
             commands::get_file_preview,
             commands::delete_files,
         ])
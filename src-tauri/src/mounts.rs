@@ -0,0 +1,219 @@
+use crate::options::ScanOptions;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 既定で走査しないファイルシステムの種類（仮想ファイルシステムと、遅いネットワーク越しの FUSE）
+pub const DEFAULT_EXCLUDED_FS_TYPES: &[&str] = &[
+    "proc",
+    "sysfs",
+    "devtmpfs",
+    "devpts",
+    "cgroup",
+    "cgroup2",
+    "debugfs",
+    "tracefs",
+    "securityfs",
+    "configfs",
+    "pstore",
+    "bpf",
+    "fusectl",
+    "autofs",
+    "mqueue",
+    "hugetlbfs",
+    "binfmt_misc",
+    "fuse.sshfs",
+    "fuse.gvfsd-fuse",
+    "fuse.portal",
+];
+
+/// マウントポイントとファイルシステムの種類
+struct Mount {
+    path: PathBuf,
+    fs_type: String,
+}
+
+/// 走査中にデバイス（ファイルシステム）の境界を越えてよいかを判定する
+/// 判定はデバイスごとに一度だけ行う
+pub struct DeviceFilter {
+    one_file_system: bool,
+    root_dev: u64,
+    excluded_fs_types: Vec<String>,
+    /// 除外する種類が指定されたときだけ読み込む
+    mounts: Option<Vec<Mount>>,
+    checked: HashMap<u64, bool>,
+}
+
+impl DeviceFilter {
+    /// `root_dev` はスキャン対象フォルダのデバイス番号（取得できない環境では 0）
+    pub fn new(options: &ScanOptions, root_dev: u64) -> Self {
+        let excluded_fs_types: Vec<String> = match &options.excluded_fs_types {
+            Some(types) => types.iter().map(|t| t.trim().to_lowercase()).collect(),
+            None => DEFAULT_EXCLUDED_FS_TYPES
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+        };
+        Self {
+            one_file_system: options.one_file_system,
+            root_dev,
+            excluded_fs_types,
+            mounts: None,
+            // スキャン対象フォルダ自体は、指定された以上どのファイルシステムでも走査する
+            checked: HashMap::from([(root_dev, true)]),
+        }
+    }
+
+    /// フォルダ `dir`（デバイス番号 `dev`）に入ってよいかどうか
+    pub fn allows(&mut self, dir: &Path, dev: u64) -> bool {
+        if dev == 0 {
+            return true;
+        }
+        if let Some(&allowed) = self.checked.get(&dev) {
+            return allowed;
+        }
+        let crosses_boundary = self.one_file_system && dev != self.root_dev;
+        let allowed = !crosses_boundary && !self.is_excluded_fs(dir);
+        self.checked.insert(dev, allowed);
+        allowed
+    }
+
+    fn is_excluded_fs(&mut self, dir: &Path) -> bool {
+        if self.excluded_fs_types.is_empty() {
+            return false;
+        }
+        let mounts = self.mounts.get_or_insert_with(load_mounts);
+        let canonical = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+        fs_type_of(mounts, &canonical)
+            .is_some_and(|fs_type| self.excluded_fs_types.iter().any(|t| t == fs_type))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn load_mounts() -> Vec<Mount> {
+    fs::read_to_string("/proc/self/mounts")
+        .map(|text| parse_mounts(&text))
+        .unwrap_or_default()
+}
+
+/// マウント一覧を取得できない環境では種類による除外を行わない
+#[cfg(not(target_os = "linux"))]
+fn load_mounts() -> Vec<Mount> {
+    Vec::new()
+}
+
+/// /proc/self/mounts の形式（"デバイス マウントポイント 種類 オプション 0 0"）を読む
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_mounts(text: &str) -> Vec<Mount> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _device = fields.next()?;
+            let path = fields.next()?;
+            let fs_type = fields.next()?;
+            Some(Mount {
+                path: PathBuf::from(unescape_mount_path(path)),
+                fs_type: fs_type.to_lowercase(),
+            })
+        })
+        .collect()
+}
+
+/// 空白などは "\040" のような8進数でエスケープされている
+fn unescape_mount_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let digits = bytes.get(i + 1..i + 4).unwrap_or_default();
+        if bytes[i] == b'\\'
+            && digits.len() == 3
+            && digits.iter().all(|b| (b'0'..=b'7').contains(b))
+        {
+            let code = digits
+                .iter()
+                .fold(0u32, |code, b| code * 8 + (b - b'0') as u32);
+            result.push(code as u8);
+            i += 4;
+            continue;
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&result).to_string()
+}
+
+/// パスを含むマウントポイントのうち最も深いもの（後からマウントされたもの優先）の種類
+fn fs_type_of<'a>(mounts: &'a [Mount], path: &Path) -> Option<&'a str> {
+    mounts
+        .iter()
+        .enumerate()
+        .filter(|(_, mount)| path.starts_with(&mount.path))
+        .max_by_key(|(index, mount)| (mount.path.components().count(), *index))
+        .map(|(_, mount)| mount.fs_type.as_str())
+}
+
+/// パスが回転式のディスク（HDD）上にあるかどうか
+/// /sys/dev/block/<major>:<minor>/queue/rotational を読む。判定できなければ false
+#[cfg(target_os = "linux")]
+pub fn is_rotational(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    let Ok(metadata) = fs::metadata(path) else {
+        return false;
+    };
+    let (major, minor) = device_numbers(metadata.dev());
+    let Ok(device) = fs::canonicalize(format!("/sys/dev/block/{major}:{minor}")) else {
+        return false;
+    };
+    // パーティションには queue がないため、親のディスクの設定を見る
+    [device.join("queue"), device.join("../queue")]
+        .iter()
+        .find_map(|queue| fs::read_to_string(queue.join("rotational")).ok())
+        .is_some_and(|flag| flag.trim() == "1")
+}
+
+/// 回転式かどうかを取得できない環境では常に false（並列に読み取る）
+#[cfg(not(target_os = "linux"))]
+pub fn is_rotational(_path: &Path) -> bool {
+    false
+}
+
+/// Linux のデバイス番号をメジャー番号とマイナー番号に分ける（glibc の major() / minor() と同じ配置）
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn device_numbers(dev: u64) -> (u64, u64) {
+    let major = ((dev >> 32) & 0xffff_f000) | ((dev >> 8) & 0xfff);
+    let minor = ((dev >> 12) & 0xffff_ff00) | (dev & 0xff);
+    (major, minor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mounts_and_lookup() {
+        let mounts = parse_mounts(
+            "/dev/sda1 / ext4 rw,relatime 0 0\n\
+             proc /proc proc rw,nosuid 0 0\n\
+             user@host:/data /home/user/My\\040Share fuse.sshfs rw 0 0\n\
+             tmpfs /tmp tmpfs rw 0 0\n",
+        );
+        assert_eq!(mounts.len(), 4);
+        assert_eq!(fs_type_of(&mounts, Path::new("/home/user")), Some("ext4"));
+        assert_eq!(fs_type_of(&mounts, Path::new("/proc/1/fd")), Some("proc"));
+        assert_eq!(
+            fs_type_of(&mounts, Path::new("/home/user/My Share/a.txt")),
+            Some("fuse.sshfs")
+        );
+        // "/tmpdata" は "/tmp" の配下ではない
+        assert_eq!(fs_type_of(&mounts, Path::new("/tmpdata")), Some("ext4"));
+    }
+
+    #[test]
+    fn test_device_numbers() {
+        assert_eq!(device_numbers(0x801), (8, 1));
+        // NVMe（メジャー番号 259）
+        assert_eq!(device_numbers(0x10303), (259, 3));
+        assert_eq!(device_numbers(0x0000_1001_0000_0101), (0x1001, 0x100001));
+    }
+}
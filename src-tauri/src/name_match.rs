@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// ファイル名の比較方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NameMatch {
+    /// ファイル名で絞り込まない
+    #[default]
+    Off,
+    /// 完全一致
+    Exact,
+    /// 大文字・小文字を区別しない
+    IgnoreCase,
+    /// 大文字・小文字に加え、" (1)" や " - コピー" などの複製時の付加文字を無視する
+    IgnoreCopySuffix,
+}
+
+/// 複製時にファイル名の末尾に付く文字列（小文字で比較する）
+/// macOS の " copy" は "hard copy" のような元からある名前と区別できないため含めない
+const COPY_SUFFIXES: &[&str] = &[" - copy", " - コピー", "のコピー"];
+
+/// 複製を示す文字列がなくても複製時の連番とみなす "(N)" の上限
+/// （"Heat (1995)" のような年などの番号を取り除かないため）
+const MAX_BARE_COPY_NUMBER: u32 = 99;
+
+/// 複製時にファイル名の先頭に付く文字列（小文字で比較する）
+const COPY_PREFIXES: &[&str] = &["copy of ", "コピー ～ ", "コピー ~ "];
+
+impl NameMatch {
+    /// グループ化に使うキーを返す（`Off` の場合は None）
+    pub fn key(&self, name: &str) -> Option<String> {
+        match self {
+            Self::Off => None,
+            Self::Exact => Some(name.to_string()),
+            Self::IgnoreCase => Some(name.to_lowercase()),
+            Self::IgnoreCopySuffix => {
+                let lower = name.to_lowercase();
+                let path = Path::new(&lower);
+                let stem = path
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let stem = strip_copy_markers(&stem);
+                match path.extension() {
+                    Some(extension) => Some(format!("{}.{}", stem, extension.to_string_lossy())),
+                    None => Some(stem),
+                }
+            }
+        }
+    }
+}
+
+/// "file (1)"、"file - Copy (2)"、"file のコピー 2"、"Copy of file" などから元の名前を取り出す
+/// すべて取り除くと空になる場合は元の名前をそのまま返す
+fn strip_copy_markers(stem: &str) -> String {
+    let mut current = stem.trim();
+    loop {
+        let before = current;
+
+        // 末尾の連番 "(1)"（複製を示す文字列に続く場合は番号の大きさを問わない）
+        if let Some(rest) = current.strip_suffix(')') {
+            if let Some(open) = rest.rfind('(') {
+                let number = &rest[open + 1..];
+                let head = rest[..open].trim_end();
+                if is_bare_copy_number(number)
+                    || (is_number(number) && COPY_SUFFIXES.iter().any(|s| head.ends_with(s)))
+                {
+                    current = head;
+                }
+            }
+        }
+        // "- copy 2" や "のコピー 2" のような、複製を示す文字列に続く連番
+        if let Some((head, tail)) = current.rsplit_once(' ') {
+            if is_number(tail) && COPY_SUFFIXES.iter().any(|suffix| head.ends_with(suffix)) {
+                current = head;
+            }
+        }
+        for suffix in COPY_SUFFIXES {
+            if let Some(rest) = current.strip_suffix(suffix) {
+                current = rest.trim_end();
+            }
+        }
+        for prefix in COPY_PREFIXES {
+            if let Some(rest) = current.strip_prefix(prefix) {
+                current = rest.trim_start();
+            }
+        }
+
+        if current.is_empty() {
+            return stem.to_string();
+        }
+        if current == before {
+            return current.to_string();
+        }
+    }
+}
+
+fn is_number(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_digit())
+}
+
+/// ブラウザやファイルマネージャーが付ける "(1)" 形式の連番か（先頭が0の番号や大きな番号は除く）
+fn is_bare_copy_number(value: &str) -> bool {
+    is_number(value)
+        && !value.starts_with('0')
+        && value
+            .parse::<u32>()
+            .is_ok_and(|number| number <= MAX_BARE_COPY_NUMBER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_suffixes_are_ignored() {
+        let key = |name: &str| NameMatch::IgnoreCopySuffix.key(name).unwrap();
+        let original = key("Report.pdf");
+        for copy in [
+            "report (1).pdf",
+            "Report(2).pdf",
+            "Report - Copy.pdf",
+            "Report - Copy (3).pdf",
+            "Report - コピー.pdf",
+            "Report - Copy (100).pdf",
+            "Report のコピー.pdf",
+            "Reportのコピー 2.pdf",
+            "Copy of Report.pdf",
+        ] {
+            assert_eq!(key(copy), original, "{}", copy);
+        }
+        // 複製を示す文字列以外は取り除かない
+        assert_ne!(key("Report 2.pdf"), original);
+        assert_ne!(key("Report.txt"), original);
+        assert_eq!(key("(1).txt"), "(1).txt");
+    }
+
+    #[test]
+    fn test_names_that_only_look_like_copies() {
+        let key = |name: &str| NameMatch::IgnoreCopySuffix.key(name).unwrap();
+        // 年や通し番号は複製時の連番ではない
+        assert_ne!(key("Heat (1995).mkv"), key("Heat (2019).mkv"));
+        assert_ne!(key("Track (01).mp3"), key("Track (02).mp3"));
+        assert_eq!(key("Heat (1995) (1).mkv"), key("Heat (1995).mkv"));
+        // "copy" で終わる元からある名前は区別できないため取り除かない
+        assert_ne!(key("hard copy.pdf"), key("hard.pdf"));
+        assert_ne!(key("hard copy 2.pdf"), key("hard.pdf"));
+    }
+
+    #[test]
+    fn test_exact_and_ignore_case() {
+        assert_eq!(NameMatch::Off.key("A.txt"), None);
+        assert_ne!(NameMatch::Exact.key("A.txt"), NameMatch::Exact.key("a.txt"));
+        assert_eq!(
+            NameMatch::IgnoreCase.key("A.txt"),
+            NameMatch::IgnoreCase.key("a.TXT")
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use crate::filter::ScanFilter;
+use crate::hasher::HashAlgorithm;
+use crate::mounts;
+use crate::name_match::NameMatch;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::thread;
+
+/// 判定方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanMode {
+    /// サイズ → 部分ハッシュ → ファイル全体のハッシュで厳密に判定
+    #[default]
+    Strict,
+    /// サイズが同じものを重複とみなす（高速）
+    SizeOnly,
+    /// ファイル名が一致するものを重複とみなす（`name_match` の指定が必須）
+    NameOnly,
+}
+
+/// 自動設定時のハッシュ計算スレッド数の上限
+const MAX_DEFAULT_HASH_THREADS: usize = 8;
+
+/// スキャンの設定（フロントエンドから JSON で受け取る）
+/// 未知のフィールドや値はエラーにし、指定ミスが黙って無視されないようにする
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScanOptions {
+    /// スキャン対象フォルダ
+    pub paths: Vec<String>,
+    /// 参照フォルダ（比較にのみ使い、中のファイルは削除しない）
+    pub reference_paths: Vec<String>,
+    pub mode: ScanMode,
+    pub name_match: NameMatch,
+    /// 検索するサブフォルダの深さ（0 なら指定フォルダの直下のみ、省略時は無制限）
+    pub max_depth: Option<usize>,
+    /// ハッシュ計算スレッド数（省略時は自動。HDD 上のフォルダが含まれていれば 1）
+    pub threads: Option<usize>,
+    /// ファイル全体のハッシュの計算方法（部分ハッシュには常に高速な xxh3 を使う）
+    pub hash_algorithm: HashAlgorithm,
+    /// ハッシュが一致したファイルをさらにバイト単位で突き合わせる（厳密モードのみ。遅いが衝突の心配がない）
+    pub verify_contents: bool,
+    /// 対象に含めるファイルのパターン（空ならすべて）
+    pub include_globs: Vec<String>,
+    /// 除外するファイル・フォルダのパターン（一致したフォルダは中を走査しない）
+    pub exclude_globs: Vec<String>,
+    /// 対象に含める拡張子（空ならすべて）
+    pub extensions: Vec<String>,
+    /// 除外する拡張子
+    pub excluded_extensions: Vec<String>,
+    /// .gitignore・.ignore・.dupignore に書かれたファイルとフォルダを除外する（.gitignore は Git リポジトリ内のみ）
+    pub respect_ignore_files: bool,
+    /// シンボリックリンクを辿る（辿らない場合、リンク先のフォルダは走査せず、リンクはリンクとしてのみ報告する）
+    pub follow_symlinks: bool,
+    /// スキャン対象フォルダと異なるファイルシステム（マウントされたドライブなど）に入らない
+    pub one_file_system: bool,
+    /// 走査しないファイルシステムの種類（None なら proc や sshfs などの組み込みの一覧）
+    pub excluded_fs_types: Option<Vec<String>>,
+    /// 隠しファイル・隠しフォルダを除外する
+    pub skip_hidden: bool,
+    /// 除外するキャッシュ・ゴミ箱などのフォルダ（None なら組み込みの一覧。"~" はホームフォルダ）
+    pub system_excludes: Option<Vec<String>>,
+    /// この値より小さいファイルは対象外（バイト）
+    pub min_size: Option<u64>,
+    /// この値より大きいファイルは対象外（バイト）
+    pub max_size: Option<u64>,
+    /// 空ファイルを重複とは別のグループとして報告する
+    pub report_empty_files: bool,
+    /// 他と重複していないハードリンクを別のグループとして報告する
+    pub report_hardlinks: bool,
+}
+
+impl ScanOptions {
+    /// 値の組み合わせを検証する（型として不正な値は deserialize の時点でエラーになる）
+    pub fn validate(&self) -> Result<(), String> {
+        if self.paths.is_empty() {
+            return Err("スキャンするフォルダが指定されていません".to_string());
+        }
+        if self.mode == ScanMode::NameOnly && self.name_match == NameMatch::Off {
+            return Err("ファイル名のみのモードでは名前の比較方法を指定してください".to_string());
+        }
+        if !self.hash_algorithm.is_cryptographic() {
+            return Err("xxh3 は絞り込み専用のため、重複の判定には使えません".to_string());
+        }
+        if self.verify_contents && self.mode != ScanMode::Strict {
+            return Err("バイト単位の検証は厳密モードでのみ使えます".to_string());
+        }
+        if self.threads == Some(0) {
+            return Err("スレッド数には1以上を指定してください".to_string());
+        }
+        if let (Some(min_size), Some(max_size)) = (self.min_size, self.max_size) {
+            if min_size > max_size {
+                return Err("最小サイズが最大サイズを超えています".to_string());
+            }
+        }
+        ScanFilter::from_options(self)?;
+        Ok(())
+    }
+
+    /// ファイルサイズが指定された範囲内かどうか
+    pub fn size_in_range(&self, size: u64) -> bool {
+        self.min_size.is_none_or(|min_size| size >= min_size)
+            && self.max_size.is_none_or(|max_size| size <= max_size)
+    }
+
+    /// 実際に使うハッシュ計算スレッド数
+    /// 自動の場合は論理コア数（上限あり）。ただし HDD では並列読み取りがシークを増やして逆に遅くなるため、
+    /// 対象・参照フォルダのいずれかが回転式のディスク上にあれば 1 にする
+    pub fn hash_threads(&self) -> usize {
+        self.threads.unwrap_or_else(|| {
+            let on_hdd = self
+                .paths
+                .iter()
+                .chain(&self.reference_paths)
+                .any(|path| mounts::is_rotational(Path::new(path)));
+            if on_hdd {
+                return 1;
+            }
+            thread::available_parallelism()
+                .map_or(1, |n| n.get())
+                .min(MAX_DEFAULT_HASH_THREADS)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options_deserialize_and_validate() {
+        let options: ScanOptions = serde_json::from_str(
+            r#"{"paths": ["/data"], "mode": "name_only", "name_match": "ignore_case"}"#,
+        )
+        .unwrap();
+        assert_eq!(options.mode, ScanMode::NameOnly);
+        assert!(options.validate().is_ok());
+
+        // 未知の値・フィールドはエラー
+        assert!(serde_json::from_str::<ScanOptions>(r#"{"mode": "fuzzy"}"#).is_err());
+        assert!(serde_json::from_str::<ScanOptions>(r#"{"recursve": true}"#).is_err());
+
+        let no_name_match = ScanOptions {
+            paths: vec!["/data".to_string()],
+            mode: ScanMode::NameOnly,
+            ..Default::default()
+        };
+        assert!(no_name_match.validate().is_err());
+        let weak_hash = ScanOptions {
+            paths: vec!["/data".to_string()],
+            hash_algorithm: HashAlgorithm::Xxh3,
+            ..Default::default()
+        };
+        assert!(weak_hash.validate().is_err());
+        assert!(ScanOptions::default().validate().is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::iter;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// `PathStore` に格納したファイルの番号
+pub type FileId = u32;
+
+/// 走査で見つかった大量のファイルのパスを、省メモリで保持する
+/// フォルダのパスは1度だけ保持し、ファイルはフォルダの番号と名前の組で持つ
+/// （数百万ファイルを `PathBuf` のまま保持すると、同じ親フォルダのパスが何百万回も複製される）
+#[derive(Default)]
+pub struct PathStore {
+    dirs: Vec<Arc<Path>>,
+    dir_ids: HashMap<Arc<Path>, u32>,
+    files: Vec<(u32, Box<OsStr>)>,
+}
+
+impl PathStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// フォルダを登録し、その番号を返す（登録済みなら同じ番号）
+    pub fn intern_dir(&mut self, dir: &Path) -> u32 {
+        if let Some(&id) = self.dir_ids.get(dir) {
+            return id;
+        }
+        let dir: Arc<Path> = Arc::from(dir);
+        let id = self.dirs.len() as u32;
+        self.dirs.push(dir.clone());
+        self.dir_ids.insert(dir, id);
+        id
+    }
+
+    /// 登録済みのフォルダ内のファイルを加える
+    pub fn push_in(&mut self, dir: u32, name: &OsStr) -> FileId {
+        let id = self.files.len() as FileId;
+        self.files.push((dir, Box::from(name)));
+        id
+    }
+
+    /// パスを親フォルダと名前に分けて加える
+    pub fn push(&mut self, path: &Path) -> FileId {
+        let dir = self.intern_dir(path.parent().unwrap_or(Path::new("")));
+        self.push_in(dir, path.file_name().unwrap_or(path.as_os_str()))
+    }
+
+    pub fn name(&self, id: FileId) -> &OsStr {
+        &self.files[id as usize].1
+    }
+
+    /// パスを組み立てる（呼ぶたびに確保するため、必要な分だけ呼ぶこと）
+    pub fn path(&self, id: FileId) -> PathBuf {
+        let (dir, name) = &self.files[id as usize];
+        self.dirs[*dir as usize].join(&**name)
+    }
+
+    /// 全ファイルをパス順に並べ、同じパスの重複を除いた番号の一覧
+    /// パスを組み立てずに比較するため、並べ替えで一時的にメモリを消費しない
+    pub fn sorted_unique_ids(&self) -> Vec<FileId> {
+        let mut ids: Vec<FileId> = (0..self.files.len() as FileId).collect();
+        ids.sort_unstable_by(|&a, &b| self.components(a).cmp(self.components(b)));
+        ids.dedup_by(|a, b| self.components(*a).eq(self.components(*b)));
+        ids
+    }
+
+    /// `Path` の比較と同じ結果になるよう、フォルダと名前をつないだ要素を返す
+    fn components(&self, id: FileId) -> impl Iterator<Item = Component<'_>> {
+        let (dir, name) = &self.files[id as usize];
+        self.dirs[*dir as usize]
+            .components()
+            .chain(iter::once(Component::Normal(name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths_are_interned_and_sorted() {
+        let mut store = PathStore::new();
+        let photos = store.intern_dir(Path::new("/data/photos"));
+        let b = store.push_in(photos, OsStr::new("b.jpg"));
+        let nested = store.push(Path::new("/data/photos/2024/a.jpg"));
+        let a = store.push(Path::new("/data/photos/a.jpg"));
+        store.push_in(photos, OsStr::new("b.jpg"));
+
+        assert_eq!(store.intern_dir(Path::new("/data/photos")), photos);
+        assert_eq!(store.path(b), PathBuf::from("/data/photos/b.jpg"));
+        assert_eq!(store.name(nested), OsStr::new("a.jpg"));
+        // `PathBuf` と同じ順序で並び、同じパスは1つにまとめられる
+        assert_eq!(store.sorted_unique_ids(), vec![nested, a, b]);
+    }
+}
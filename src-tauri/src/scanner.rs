@@ -1,9 +1,23 @@
+use crate::filter::ScanFilter;
+use crate::hash_cache::{device_and_inode, link_count, FileStamp, HashCache};
+use crate::hasher::{Digest, HashAlgorithm};
+use crate::ignore_rules::IgnoreRules;
+use crate::mounts::DeviceFilter;
+use crate::name_match::NameMatch;
+use crate::options::{ScanMode, ScanOptions};
+use crate::path_store::{FileId, PathStore};
 use serde::Serialize;
-use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::io::Read;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use xxhash_rust::xxh3::{xxh3_128, Xxh3};
 
 /// 個別ファイルの情報
 #[derive(Debug, Clone, Serialize)]
@@ -13,6 +27,102 @@ pub struct FileInfo {
     pub size: u64,
     pub hash: String,
     pub extension: String,
+    /// このファイルを見つけたスキャン対象フォルダ
+    pub root: String,
+    /// 参照フォルダ内のファイル（削除不可）
+    /// シンボリックリンクを辿って見つけた、スキャン対象フォルダの外にあるファイルも含む
+    pub is_reference: bool,
+    /// このファイルを指すシンボリックリンク（重複ではない）
+    pub symlinks: Vec<String>,
+    /// デバイス番号と inode（取得できない環境では 0）
+    pub dev: u64,
+    pub ino: u64,
+    /// 同じ実体を共有する他のパス（ハードリンク。`path` と合わせて1つのファイルとして扱う）
+    pub hardlinks: Vec<String>,
+}
+
+/// 比較から外した理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// アクセス権がない
+    PermissionDenied,
+    /// スキャン中に削除・移動された
+    Vanished,
+    /// その他の読み取りエラー
+    IoError,
+    /// ハッシュの計算中・計算後に内容が変更された（報告するハッシュやサイズと一致しない）
+    ChangedDuringScan,
+}
+
+impl SkipReason {
+    fn of(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            io::ErrorKind::NotFound => Self::Vanished,
+            _ => Self::IoError,
+        }
+    }
+}
+
+/// 読み取れなかった、またはスキャン中に変更されたため比較から外したファイル・フォルダ
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedPath {
+    pub path: String,
+    pub reason: SkipReason,
+    pub message: String,
+}
+
+/// 理由ごとのスキップ件数
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SkippedCounts {
+    pub permission_denied: usize,
+    pub vanished: usize,
+    pub io_error: usize,
+    pub changed_during_scan: usize,
+}
+
+/// スキャン全体の集計
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ScanStats {
+    /// 見つけたファイル数（フィルタで除外したものは含まない）
+    pub files_walked: u64,
+    pub dirs_walked: u64,
+    /// サイズを調べたファイルの合計サイズ
+    pub bytes_examined: u64,
+    /// 部分ハッシュ・全体ハッシュで実際に読んだバイト数
+    pub bytes_hashed: u64,
+    /// 全体ハッシュをキャッシュから再利用し、読まずに済んだファイルの合計サイズ
+    pub bytes_from_cache: u64,
+    /// 重複グループの数（空ファイルやハードリンクの別枠は含まない）
+    pub groups: usize,
+    /// 削除できる重複ファイルの数
+    pub redundant_copies: usize,
+    /// 重複ファイルを削除した場合に空く容量
+    pub reclaimable_bytes: u64,
+}
+
+/// 段階ごとの所要時間（ミリ秒）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StageTimings {
+    pub collecting_ms: u64,
+    pub grouping_ms: u64,
+    pub prefiltering_ms: u64,
+    pub hashing_ms: u64,
+    pub total_ms: u64,
+}
+
+/// スキャン結果
+/// `skipped` が空でなければ、見つからなかった重複がある可能性がある
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanReport {
+    pub groups: Vec<DuplicateGroup>,
+    pub skipped: Vec<SkippedPath>,
+    pub skipped_counts: SkippedCounts,
+    pub stats: ScanStats,
+    pub timings: StageTimings,
+    /// このスキャンで使った設定（ログや実行結果の比較用）
+    pub options: ScanOptions,
 }
 
 /// 重複グループ（同一内容を持つファイル群）
@@ -21,155 +131,1429 @@ pub struct DuplicateGroup {
     pub hash: String,
     pub size: u64,
     pub files: Vec<FileInfo>,
+    pub kind: GroupKind,
+    /// `hash` の計算方法（ハッシュで判定していないグループでは None）
+    pub hash_algorithm: Option<HashAlgorithm>,
+    /// 全ファイルの内容をバイト単位で突き合わせて一致を確認済みか
+    pub verified: bool,
+}
+
+impl DuplicateGroup {
+    /// 1つを残して削除できるファイルの数（参照フォルダにコピーがあれば参照以外のすべて）
+    /// ハードリンクは1ファイルにまとめてあるため、実体の数で数える
+    pub fn redundant_copies(&self) -> usize {
+        self.deletable_sizes().len()
+    }
+
+    /// `redundant_copies` のファイルを削除した場合に空く容量
+    pub fn reclaimable_bytes(&self) -> u64 {
+        self.deletable_sizes().iter().sum()
+    }
+
+    fn deletable_sizes(&self) -> Vec<u64> {
+        if self.kind != GroupKind::Duplicate {
+            return Vec::new();
+        }
+        let mut sizes: Vec<u64> = self
+            .files
+            .iter()
+            .filter(|f| !f.is_reference)
+            .map(|f| f.size)
+            .collect();
+        if !self.files.iter().any(|f| f.is_reference) {
+            // 最も大きいファイルを残す
+            sizes.sort_unstable();
+            sizes.pop();
+        }
+        sizes
+    }
+}
+
+/// グループの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupKind {
+    /// 内容（またはモードに応じてサイズ・名前）が一致するファイル群
+    Duplicate,
+    /// 空ファイルの一覧（重複ではなく、整理候補として別枠で報告する）
+    EmptyFiles,
+    /// 1つの実体を共有するハードリンク（容量を消費しないため重複ではない）
+    Hardlinks,
+}
+
+/// スキャンの進行段階
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanPhase {
+    /// ファイルの列挙中
+    Collecting,
+    /// ファイルサイズでグループ化中
+    Grouping,
+    /// 部分ハッシュによる絞り込み中
+    Prefiltering,
+    /// ハッシュ計算中
+    Hashing,
+    /// 完了
+    Done,
 }
 
-/// フォルダ内のファイルを再帰的に（または直下のみ）収集するヘルパー
-fn collect_files(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>, String> {
-    let mut files = Vec::new();
-    if dir.is_dir() {
-        // fs::read_dir がエラーを返した場合はそのディレクトリはスキップ（権限エラー等への配慮）
-        if let Ok(entries) = fs::read_dir(dir) {
-            for entry in entries.filter_map(|e| e.ok()) {
-                let path = entry.path();
-                if path.is_file() {
-                    files.push(path);
-                } else if path.is_dir() && recursive {
-                    if let Ok(mut sub_files) = collect_files(&path, true) {
-                        files.append(&mut sub_files);
+/// スキャンの進捗情報（フロントエンドへ逐次通知する）
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanProgress {
+    pub phase: ScanPhase,
+    pub files_discovered: u64,
+    pub files_hashed: u64,
+    pub files_to_hash: u64,
+    /// 処理済みのバイト数（進捗表示用。キャッシュから再利用したファイルのサイズを含む）
+    pub bytes_hashed: u64,
+    pub bytes_to_hash: u64,
+    /// 読み取れない・変更されたなどで比較から外したファイル・フォルダの数
+    pub files_skipped: u64,
+    pub current_path: Option<String>,
+}
+
+/// 進捗通知の最小間隔（Webview がイベントで溢れないように間引く）
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 進捗を集計し、一定間隔ごとにコールバックへ通知する
+/// ハッシュ計算のワーカースレッド間で共有するため内部で排他制御する
+struct ProgressReporter<'a> {
+    state: Mutex<ReporterState>,
+    on_progress: &'a (dyn Fn(&ScanProgress) + Sync),
+}
+
+struct ReporterState {
+    progress: ScanProgress,
+    last_emit: Option<Instant>,
+    skipped: Vec<SkippedPath>,
+    dirs_walked: u64,
+    /// 段階ごとにリセットされない読み取りバイト数の累計
+    total_bytes_hashed: u64,
+    /// キャッシュから再利用したファイルサイズの累計
+    total_bytes_from_cache: u64,
+    started: Instant,
+    phase_started: Instant,
+    timings: StageTimings,
+}
+
+impl<'a> ProgressReporter<'a> {
+    fn new(on_progress: &'a (dyn Fn(&ScanProgress) + Sync)) -> Self {
+        Self {
+            state: Mutex::new(ReporterState {
+                progress: ScanProgress {
+                    phase: ScanPhase::Collecting,
+                    files_discovered: 0,
+                    files_hashed: 0,
+                    files_to_hash: 0,
+                    bytes_hashed: 0,
+                    bytes_to_hash: 0,
+                    files_skipped: 0,
+                    current_path: None,
+                },
+                last_emit: None,
+                skipped: Vec::new(),
+                dirs_walked: 0,
+                total_bytes_hashed: 0,
+                total_bytes_from_cache: 0,
+                started: Instant::now(),
+                phase_started: Instant::now(),
+                timings: StageTimings::default(),
+            }),
+            on_progress,
+        }
+    }
+
+    /// 段階の切り替えは間引かずに必ず通知する
+    fn set_phase(&self, phase: ScanPhase) {
+        let mut state = self.state.lock().unwrap();
+        // 終わった段階の所要時間を記録する
+        let elapsed = state.phase_started.elapsed().as_millis() as u64;
+        match state.progress.phase {
+            ScanPhase::Collecting => state.timings.collecting_ms += elapsed,
+            ScanPhase::Grouping => state.timings.grouping_ms += elapsed,
+            ScanPhase::Prefiltering => state.timings.prefiltering_ms += elapsed,
+            ScanPhase::Hashing => state.timings.hashing_ms += elapsed,
+            ScanPhase::Done => {}
+        }
+        state.phase_started = Instant::now();
+        state.timings.total_ms = state.started.elapsed().as_millis() as u64;
+        state.progress.phase = phase;
+        state.progress.current_path = None;
+        self.emit(state);
+    }
+
+    /// ハッシュ計算対象の総数を設定し、計算済みの件数をリセットする
+    fn set_hash_totals(&self, files: u64, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.progress.files_hashed = 0;
+        state.progress.bytes_hashed = 0;
+        state.progress.files_to_hash = files;
+        state.progress.bytes_to_hash = bytes;
+    }
+
+    /// 読み取る予定のバイト数を追加する（バイト単位の検証はグループが確定するまで量が分からないため）
+    fn add_bytes_to_hash(&self, bytes: u64) {
+        self.state.lock().unwrap().progress.bytes_to_hash += bytes;
+    }
+
+    fn set_current_path(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        state.progress.current_path = Some(path.to_string_lossy().to_string());
+    }
+
+    fn file_discovered(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        state.progress.files_discovered += 1;
+        state.progress.current_path = Some(path.to_string_lossy().to_string());
+        self.tick(state);
+    }
+
+    fn dir_walked(&self) {
+        self.state.lock().unwrap().dirs_walked += 1;
+    }
+
+    fn bytes_hashed(&self, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.progress.bytes_hashed += bytes;
+        state.total_bytes_hashed += bytes;
+        self.tick(state);
+    }
+
+    /// キャッシュを再利用したファイルは、読み取らずに進捗だけを進める
+    fn bytes_from_cache(&self, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.progress.bytes_hashed += bytes;
+        state.total_bytes_from_cache += bytes;
+        self.tick(state);
+    }
+
+    fn file_hashed(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.progress.files_hashed += 1;
+        self.tick(state);
+    }
+
+    /// 読み取れなかったファイル・フォルダを記録する
+    fn path_skipped(&self, path: &Path, error: &io::Error) {
+        let mut state = self.state.lock().unwrap();
+        state.progress.files_skipped += 1;
+        state.skipped.push(SkippedPath {
+            path: path.to_string_lossy().to_string(),
+            reason: SkipReason::of(error),
+            message: error.to_string(),
+        });
+        self.tick(state);
+    }
+
+    /// スキャン中に変更されたファイルを比較から外したことを記録する
+    fn path_changed(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        state.progress.files_skipped += 1;
+        state.skipped.push(SkippedPath {
+            path: path.to_string_lossy().to_string(),
+            reason: SkipReason::ChangedDuringScan,
+            message: "スキャン中に変更されました".to_string(),
+        });
+        self.tick(state);
+    }
+
+    /// 走査・読み取りの累計と段階ごとの所要時間
+    fn totals(&self) -> (ScanStats, StageTimings) {
+        let state = self.state.lock().unwrap();
+        let stats = ScanStats {
+            files_walked: state.progress.files_discovered,
+            dirs_walked: state.dirs_walked,
+            bytes_hashed: state.total_bytes_hashed,
+            bytes_from_cache: state.total_bytes_from_cache,
+            ..Default::default()
+        };
+        (stats, state.timings.clone())
+    }
+
+    /// 記録したスキップをパス順に取り出す
+    fn take_skipped(&self) -> Vec<SkippedPath> {
+        let mut skipped = std::mem::take(&mut self.state.lock().unwrap().skipped);
+        skipped.sort_by(|a, b| a.path.cmp(&b.path));
+        skipped
+    }
+
+    /// 前回の通知から一定時間が経過していれば通知する
+    fn tick(&self, state: MutexGuard<ReporterState>) {
+        let due = state
+            .last_emit
+            .is_none_or(|last| last.elapsed() >= PROGRESS_INTERVAL);
+        if due {
+            self.emit(state);
+        }
+    }
+
+    /// 進捗の写しを取ってからロックを手放して通知する（通知先の処理中に他のスレッドを止めないため）
+    fn emit(&self, mut state: MutexGuard<ReporterState>) {
+        state.last_emit = Some(Instant::now());
+        let progress = state.progress.clone();
+        drop(state);
+        (self.on_progress)(&progress);
+    }
+}
+
+/// キャンセル時に返すエラーメッセージ
+pub const CANCELLED_MESSAGE: &str = "スキャンがキャンセルされました";
+
+/// キャンセルが要求されていればエラーを返す
+fn check_cancelled(cancel: &AtomicBool) -> Result<(), String> {
+    if cancel.load(Ordering::Relaxed) {
+        return Err(CANCELLED_MESSAGE.to_string());
+    }
+    Ok(())
+}
+
+/// ファイル読み取り中のキャンセル確認（読み取りエラーと同じ経路で中断する）
+fn check_cancelled_io(cancel: &AtomicBool) -> io::Result<()> {
+    if cancel.load(Ordering::Relaxed) {
+        return Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            CANCELLED_MESSAGE,
+        ));
+    }
+    Ok(())
+}
+
+/// 走査済みのフォルダを識別する値
+/// Unix では (デバイス, inode)。取得できない環境では正規化したパスで代用する
+#[derive(PartialEq, Eq, Hash)]
+enum DirIdentity {
+    Inode(u64, u64),
+    Path(PathBuf),
+}
+
+impl DirIdentity {
+    fn of(path: &Path, metadata: &fs::Metadata) -> Option<Self> {
+        match device_and_inode(metadata) {
+            (0, 0) => fs::canonicalize(path).ok().map(Self::Path),
+            (dev, ino) => Some(Self::Inode(dev, ino)),
+        }
+    }
+}
+
+/// スキャン対象フォルダ1つ分の走査
+/// `filter` で除外されたフォルダは中を走査しない
+struct Walker<'a> {
+    root: &'a Path,
+    roots: &'a [ScanRoot],
+    /// 走査するサブフォルダの深さ（0 なら直下のみ、None なら無制限）
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    filter: &'a ScanFilter,
+    /// ファイルシステムの境界を越えるかどうかの判定
+    devices: DeviceFilter,
+    cancel: &'a AtomicBool,
+    reporter: &'a ProgressReporter<'a>,
+    /// 走査済みのフォルダ。シンボリックリンクによる循環や、同じフォルダの二重走査を防ぐ
+    visited: HashSet<DirIdentity>,
+    /// 見つかったファイル（全スキャン対象フォルダで共有する）
+    files: &'a mut PathStore,
+    /// 見つかったシンボリックリンクと、その先のファイル
+    symlinks: Vec<(PathBuf, PathBuf)>,
+}
+
+/// 未走査のフォルダと、その深さ・親フォルダまでの無視ルール
+type PendingDir = (PathBuf, usize, Option<IgnoreRules>);
+
+impl Walker<'_> {
+    /// フォルダ内のファイルを `max_depth` の深さまで収集する
+    /// 深い階層でもスタックを使い果たさないよう、再帰せずに未走査のフォルダを積んで順に処理する
+    fn walk(&mut self, root: &Path, ignore_rules: Option<IgnoreRules>) -> Result<(), String> {
+        let mut pending: Vec<PendingDir> = vec![(root.to_path_buf(), 0, ignore_rules)];
+        while let Some((dir, depth, ignore_rules)) = pending.pop() {
+            check_cancelled(self.cancel)?;
+            self.walk_dir(&dir, depth, ignore_rules, &mut pending)?;
+        }
+        Ok(())
+    }
+
+    /// フォルダ1つ分を読み、ファイルを収集してサブフォルダを `pending` に積む
+    /// （`depth` はスキャン対象フォルダを 0 とした深さ）
+    fn walk_dir(
+        &mut self,
+        dir: &Path,
+        depth: usize,
+        ignore_rules: Option<IgnoreRules>,
+        pending: &mut Vec<PendingDir>,
+    ) -> Result<(), String> {
+        let metadata = match fs::metadata(dir) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                self.reporter.path_skipped(dir, &e);
+                return Ok(());
+            }
+        };
+        if !metadata.is_dir() || !self.devices.allows(dir, device_and_inode(&metadata).0) {
+            return Ok(());
+        }
+        if let Some(identity) = DirIdentity::of(dir, &metadata) {
+            if !self.visited.insert(identity) {
+                return Ok(());
+            }
+        }
+        self.reporter.dir_walked();
+
+        // 無視ファイルに従う場合は、このフォルダの .gitignore などを加える
+        let ignore_rules = ignore_rules.map(|rules| rules.enter(dir));
+        let is_ignored = |path: &Path, is_dir: bool| {
+            ignore_rules
+                .as_ref()
+                .is_some_and(|rules| rules.is_ignored(path, is_dir))
+        };
+        let descend = self.max_depth.is_none_or(|max_depth| depth < max_depth);
+        // 読み取れないフォルダはスキップし、理由を記録する（権限エラー等への配慮）
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.reporter.path_skipped(dir, &e);
+                return Ok(());
+            }
+        };
+        let dir_id = self.files.intern_dir(dir);
+        for entry in entries {
+            check_cancelled(self.cancel)?;
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.reporter.path_skipped(dir, &e);
+                    continue;
+                }
+            };
+            let path = entry.path();
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(e) => {
+                    self.reporter.path_skipped(&path, &e);
+                    continue;
+                }
+            };
+
+            if file_type.is_symlink() {
+                let resolved =
+                    fs::canonicalize(&path).and_then(|target| Ok((target, fs::metadata(&path)?)));
+                let (target, target_metadata) = match resolved {
+                    Ok(resolved) => resolved,
+                    // リンク先が存在しない（壊れている）リンクは比較する内容がないため無視する
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    // 権限がない・循環しているなど、リンク先を読めない場合は理由を記録する
+                    Err(e) => {
+                        self.reporter.path_skipped(&path, &e);
+                        continue;
                     }
+                };
+                let target = to_scan_path(target, self.roots);
+                if target_metadata.is_file() {
+                    if !self.filter.allows_file(&path, self.root) || is_ignored(&path, false) {
+                        continue;
+                    }
+                    // リンク自体はコピーではないため、比較するのはリンク先のファイルのみ
+                    if self.follow_symlinks {
+                        self.reporter.file_discovered(&target);
+                        self.files.push(&target);
+                    }
+                    self.symlinks.push((path, target));
+                } else if target_metadata.is_dir()
+                    && self.follow_symlinks
+                    && descend
+                    && self.filter.allows_dir(&path, self.root)
+                    && !is_ignored(&path, true)
+                {
+                    // リンク先のパスで走査し、同じファイルが別のパスで二重に見つからないようにする
+                    pending.push((target, depth + 1, ignore_rules.clone()));
                 }
+            } else if file_type.is_file() {
+                if self.filter.allows_file(&path, self.root) && !is_ignored(&path, false) {
+                    self.reporter.file_discovered(&path);
+                    self.files.push_in(dir_id, &entry.file_name());
+                }
+            } else if file_type.is_dir()
+                && descend
+                && self.filter.allows_dir(&path, self.root)
+                && !is_ignored(&path, true)
+            {
+                pending.push((path, depth + 1, ignore_rules.clone()));
             }
         }
+        Ok(())
     }
-    Ok(files)
 }
 
-/// 指定フォルダ以下のファイルを走査し、重複グループを返す
-/// アルゴリズム：
-///   ステージ1: ファイル名でグループ化（同名ファイルの検出）
-///   ステージ2: ファイルサイズでグループ化（同サイズのみが候補）
-///   ステージ3: SHA-256ハッシュで最終判定 (strict モード時のみ)
-pub fn scan_for_duplicates(folder_path: &str, mode: &str, recursive: bool) -> Result<Vec<DuplicateGroup>, String> {
-    let path = Path::new(folder_path);
-    if !path.exists() {
-        return Err(format!("フォルダが存在しません: {}", folder_path));
+/// スキャンの実行中に呼び出し側と共有するもの
+pub struct ScanContext<'a> {
+    /// 全体ハッシュのキャッシュ（None ならキャッシュを使わない）
+    pub cache: Option<&'a Mutex<HashCache>>,
+    /// 立てられるとファイル間・読み取りバッファ間で中断する
+    pub cancel: &'a AtomicBool,
+    /// 進捗の通知先（間引いて呼ばれる）
+    pub on_progress: &'a (dyn Fn(&ScanProgress) + Sync),
+    /// 重複グループが確定するたびに呼ばれる（サイズの大きいものから順）
+    /// スキャン結果の `groups` にも同じグループが含まれる
+    pub on_group: &'a (dyn Fn(&DuplicateGroup) + Sync),
+}
+
+/// スキャン対象フォルダ
+struct ScanRoot {
+    path: PathBuf,
+    /// 正規化したパス（シンボリックリンクのリンク先との比較用）
+    canonical: PathBuf,
+    /// 参照フォルダ（読み取り専用。ここにあるファイルは削除候補にしない）
+    reference: bool,
+}
+
+/// スキャン対象フォルダと参照フォルダを検証し、重複や入れ子を取り除く
+/// 深さが無制限の場合は、同じ種類のフォルダの配下にあるフォルダは親側の走査に含まれるため除外する
+fn normalize_roots(
+    folder_paths: &[String],
+    reference_paths: &[String],
+    max_depth: Option<usize>,
+) -> Result<Vec<ScanRoot>, String> {
+    let mut roots: Vec<ScanRoot> = Vec::new();
+    let tagged = folder_paths
+        .iter()
+        .map(|p| (p, false))
+        .chain(reference_paths.iter().map(|p| (p, true)));
+    for (folder_path, reference) in tagged {
+        let path = Path::new(folder_path);
+        if !path.exists() {
+            return Err(format!("フォルダが存在しません: {}", folder_path));
+        }
+        if !path.is_dir() {
+            return Err(format!("ディレクトリではありません: {}", folder_path));
+        }
+        // 比較には正規化したパスを使い、走査と表示には指定されたパスを使う
+        roots.push(ScanRoot {
+            path: path.to_path_buf(),
+            canonical: fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()),
+            reference,
+        });
     }
-    if !path.is_dir() {
-        return Err(format!("ディレクトリではありません: {}", folder_path));
+
+    // 祖先は子孫より前に並ぶため、先に残したフォルダとだけ比較すればよい
+    // 同じフォルダが両方に指定された場合は参照フォルダとして扱う
+    roots.sort_by(|a, b| {
+        a.canonical
+            .cmp(&b.canonical)
+            .then(b.reference.cmp(&a.reference))
+    });
+    let mut kept: Vec<ScanRoot> = Vec::new();
+    for root in roots {
+        let covered = kept.iter().any(|kept_root| {
+            kept_root.canonical == root.canonical
+                || (max_depth.is_none()
+                    && kept_root.reference == root.reference
+                    && root.canonical.starts_with(&kept_root.canonical))
+        });
+        if !covered {
+            kept.push(root);
+        }
     }
+    Ok(kept)
+}
+
+/// 正規化したパスを、スキャン対象フォルダ内であれば指定されたフォルダのパスを基準にした形に直す
+/// （シンボリックリンクのリンク先を、直接見つけた同じファイルと同じパスで扱うため）
+fn to_scan_path(canonical: PathBuf, roots: &[ScanRoot]) -> PathBuf {
+    let root = roots
+        .iter()
+        .filter(|root| canonical.starts_with(&root.canonical))
+        .max_by_key(|root| root.canonical.components().count());
+    match root {
+        Some(root) => match canonical.strip_prefix(&root.canonical) {
+            Ok(relative) if relative.as_os_str().is_empty() => root.path.clone(),
+            Ok(relative) => root.path.join(relative),
+            Err(_) => canonical,
+        },
+        None => canonical,
+    }
+}
+
+/// ファイルを見つけたスキャン対象フォルダ（最も深く一致するもの）を返す
+fn root_of<'a>(file_path: &Path, roots: &'a [ScanRoot]) -> Option<&'a ScanRoot> {
+    roots
+        .iter()
+        .filter(|root| file_path.starts_with(&root.path))
+        .max_by_key(|root| root.path.components().count())
+}
+
+/// 指定フォルダ以下のファイルを走査し、重複グループを返す
+/// アルゴリズム：
+///   ステージ1: ファイル名でグループ化（`name_match` 指定時のみ。name_only モードではここで確定）
+///   ステージ2: ファイルサイズでグループ化（同サイズのみが候補。空ファイルとサイズ範囲外は除く）
+///   ステージ2.5: 先頭・中央・末尾のみの部分ハッシュで絞り込み (strict モード時のみ)
+///   ステージ3: `options.hash_algorithm` のハッシュで最終判定 (strict モード時のみ)
+///   ステージ4: ハッシュが一致したファイルをバイト単位で突き合わせる (`verify_contents` 指定時のみ)
+/// 進捗は `context.on_progress` に間引いて通知される
+/// `context.cancel` が立てられると中断し、`CANCELLED_MESSAGE` を返す
+/// ハッシュ計算は `options.threads` 本のワーカーで並列に行うが、結果の順序はスレッド数に依存しない
+/// `context.cache` を渡すと、メタデータが変わっていないファイルは保存済みのハッシュを再利用する
+/// 複数のフォルダを渡すとまとめて走査し、フォルダをまたいだ重複も検出する
+/// 参照フォルダ内のファイルは比較対象にのみ使い、
+/// 参照フォルダのファイルだけで構成されるグループは報告しない
+/// 同じ inode を共有するハードリンクは1つのファイルにまとめ、他のパスは `FileInfo::hardlinks` に入れる
+pub fn scan_for_duplicates(
+    options: &ScanOptions,
+    context: &ScanContext,
+) -> Result<ScanReport, String> {
+    options.validate()?;
+    let ScanOptions {
+        ref paths,
+        ref reference_paths,
+        mode,
+        name_match,
+        max_depth,
+        ..
+    } = *options;
+    let ScanContext {
+        cancel,
+        on_progress,
+        on_group,
+        ..
+    } = *context;
+    let roots = normalize_roots(paths, reference_paths, max_depth)?;
+    let filter = ScanFilter::from_options(options)?;
+
+    let reporter = ProgressReporter::new(on_progress);
 
     // ファイルを収集
-    let entries = collect_files(path, recursive)?;
+    reporter.set_phase(ScanPhase::Collecting);
+    let mut store = PathStore::new();
+    let mut symlinks: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for root in &roots {
+        let ignore_rules = options
+            .respect_ignore_files
+            .then(|| IgnoreRules::for_ancestors_of(&root.path));
+        let root_dev = fs::metadata(&root.path).map_or(0, |m| device_and_inode(&m).0);
+        let mut walker = Walker {
+            root: &root.path,
+            roots: &roots,
+            max_depth,
+            follow_symlinks: options.follow_symlinks,
+            filter: &filter,
+            devices: DeviceFilter::new(options, root_dev),
+            cancel,
+            reporter: &reporter,
+            visited: HashSet::new(),
+            files: &mut store,
+            symlinks: Vec::new(),
+        };
+        walker.walk(&root.path, ignore_rules)?;
+        for (link, target) in walker.symlinks {
+            symlinks.entry(target).or_default().push(link);
+        }
+    }
+    for links in symlinks.values_mut() {
+        links.sort();
+    }
+    // 種類の異なるフォルダが入れ子になっている場合や、シンボリックリンクを辿った場合に
+    // 同じファイルが二度見つかるため取り除く
+    let entries = store.sorted_unique_ids();
 
-    // ステージ2: ファイルサイズでグループ化
-    let mut size_groups: HashMap<u64, Vec<PathBuf>> = HashMap::new();
-    for file_path in &entries {
-        if let Ok(metadata) = fs::metadata(file_path) {
-            size_groups
-                .entry(metadata.len())
-                .or_default()
-                .push(file_path.clone());
+    // 以降のステージでは、ファイルのパスは `store` の番号で持ち、必要になったときだけ組み立てる
+    reporter.set_phase(ScanPhase::Grouping);
+    let mut sized_entries: Vec<(FileId, u64)> = Vec::new();
+    let mut empty_files: Vec<FileId> = Vec::new();
+    // 同じ実体（デバイス, inode）を共有するハードリンクは、最初に見つけたパスに代表させる
+    // リンクが1つしかないファイルは記録しない（大半のファイルはこちらで、記録するとメモリを消費する）
+    // ただしフォルダが入れ子の場合は、同じファイルが別の表記のパスで見つかりうるためすべて記録する
+    let nested_roots = roots.iter().enumerate().any(|(i, a)| {
+        roots
+            .iter()
+            .enumerate()
+            .any(|(j, b)| i != j && b.canonical.starts_with(&a.canonical))
+    });
+    let mut inodes: HashMap<(u64, u64), FileId> = HashMap::new();
+    let mut hardlinks: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    let mut bytes_examined = 0;
+    for id in entries {
+        check_cancelled(cancel)?;
+        let file_path = store.path(id);
+        let metadata = match fs::metadata(&file_path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                reporter.path_skipped(&file_path, &e);
+                continue;
+            }
+        };
+        let identity = device_and_inode(&metadata);
+        if identity != (0, 0) && (nested_roots || link_count(&metadata) > 1) {
+            if let Some(&representative) = inodes.get(&identity) {
+                hardlinks
+                    .entry(store.path(representative))
+                    .or_default()
+                    .push(file_path);
+                continue;
+            }
+            inodes.insert(identity, id);
+        }
+        let size = metadata.len();
+        bytes_examined += size;
+        if size == 0 {
+            // 空ファイルは内容を比較しても意味がないため、重複グループとは別に扱う
+            empty_files.push(id);
+        } else if options.size_in_range(size) {
+            sized_entries.push((id, size));
         }
     }
+    drop(inodes);
 
-    // 同サイズのファイルが2つ以上あるグループのみ残す
-    let candidates: Vec<(u64, Vec<PathBuf>)> = size_groups
-        .into_iter()
-        .filter(|(_, files)| files.len() >= 2)
-        .collect();
+    // ステージ1: ファイル名でグループ化（比較しない場合は全体を1つのグループとして扱う）
+    let name_groups: Vec<(String, Vec<(FileId, u64)>)> = if name_match == NameMatch::Off {
+        vec![(String::new(), sized_entries)]
+    } else {
+        let mut by_name: BTreeMap<String, Vec<(FileId, u64)>> = BTreeMap::new();
+        for (id, size) in sized_entries {
+            if let Some(key) = name_match.key(&store.name(id).to_string_lossy()) {
+                by_name.entry(key).or_default().push((id, size));
+            }
+        }
+        by_name
+            .into_iter()
+            .filter(|(_, files)| files.len() >= 2)
+            .collect()
+    };
 
-    let mut duplicate_groups: Vec<DuplicateGroup> = Vec::new();
+    // 確定したグループは、スキャンの完了を待たずに `on_group` へ通知する
+    let confirmed: Mutex<Vec<DuplicateGroup>> = Mutex::new(Vec::new());
+    let confirm = |mut group: DuplicateGroup| {
+        // 参照フォルダのファイルしか含まないグループは整理の対象外
+        if !group.files.iter().any(|f| !f.is_reference) {
+            return;
+        }
+        annotate_links(&mut group, &hardlinks, &symlinks);
+        on_group(&group);
+        confirmed.lock().unwrap().push(group);
+    };
 
-    if mode == "size_only" {
-        // ステージ3をスキップし、サイズが同じものをそのままグループ化
-        for (size, files) in candidates {
-            let file_infos: Vec<FileInfo> = files
+    if mode == ScanMode::NameOnly {
+        // 名前が一致したものをそのままグループ化（ハッシュはダミー）
+        for (key, files) in &name_groups {
+            let files: Vec<(PathBuf, u64)> = files
                 .iter()
-                .map(|fp| {
-                    let name = fp.file_name().unwrap_or_default().to_string_lossy().to_string();
-                    let extension = fp.extension().unwrap_or_default().to_string_lossy().to_string();
-                    FileInfo {
-                        path: fp.to_string_lossy().to_string(),
-                        name,
-                        size,
-                        hash: format!("size_{}", size), // ダミーハッシュ
-                        extension,
-                    }
-                })
+                .map(|&(id, size)| (store.path(id), size))
                 .collect();
-
-            duplicate_groups.push(DuplicateGroup {
-                hash: format!("size_{}", size),
-                size,
-                files: file_infos,
-            });
+            confirm(build_name_group(key, &files, &roots));
         }
     } else {
-        // ステージ3: strictモードの場合は、SHA-256ハッシュで厳密に最終判定
-        for (size, files) in candidates {
-            let mut hash_groups: HashMap<String, Vec<PathBuf>> = HashMap::new();
-
-            for file_path in &files {
-                match calculate_hash(file_path) {
-                    Ok(hash) => {
-                        hash_groups.entry(hash).or_default().push(file_path.clone());
-                    }
-                    Err(_) => continue, // ハッシュ計算に失敗したファイルはスキップ
+        // ステージ2: ファイルサイズでグループ化（名前のグループごとに行う）
+        // 同サイズのファイルが2つ以上あるグループのみ残す
+        // パスはグループを確定するときまで組み立てず、ハッシュの計算中も番号で持つ
+        let mut candidates: Vec<(u64, Vec<FileId>)> = Vec::new();
+        for (_, files) in name_groups {
+            let mut size_groups: HashMap<u64, Vec<FileId>> = HashMap::new();
+            for (id, size) in files {
+                size_groups.entry(size).or_default().push(id);
+            }
+            candidates.extend(size_groups.into_iter().filter(|(_, ids)| ids.len() >= 2));
+        }
+
+        // 結果を決定的にするため、サイズの大きい順に並べておく
+        // （各グループ内の番号は `sorted_unique_ids` の順のまま、つまりパス順に並んでいる）
+        candidates.sort_by_key(|(size, _)| Reverse(*size));
+
+        if mode == ScanMode::SizeOnly {
+            // ステージ3をスキップし、サイズが同じものをそのままグループ化（ハッシュはダミー）
+            // 収集後にサイズが変わったファイルは外す
+            for (size, ids) in &candidates {
+                let files =
+                    unchanged_files(ids.iter().map(|&id| (id, None)), &store, *size, &reporter);
+                if files.len() >= 2 {
+                    confirm(build_group(format!("size_{}", size), *size, &files, &roots));
                 }
             }
+        } else {
+            find_identical_groups(
+                &candidates,
+                &store,
+                &roots,
+                options,
+                context,
+                &reporter,
+                &confirm,
+            )?;
+        }
+    }
+
+    if options.report_empty_files && !empty_files.is_empty() {
+        let empty_files: Vec<PathBuf> = empty_files.iter().map(|&id| store.path(id)).collect();
+        let mut group = build_group("empty".to_string(), 0, &empty_files, &roots);
+        group.kind = GroupKind::EmptyFiles;
+        confirm(group);
+    }
+
+    if options.report_hardlinks {
+        // 他のファイルと重複していないハードリンクは、実体1つのグループとして別枠で報告する
+        let grouped: HashSet<String> = confirmed
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|g| g.files.iter().map(|f| f.path.clone()))
+            .collect();
+        let mut hardlink_groups = Vec::new();
+        for representative in hardlinks.keys() {
+            if grouped.contains(representative.to_string_lossy().as_ref()) {
+                continue;
+            }
+            let size = fs::metadata(representative).map_or(0, |m| m.len());
+            if size > 0 && options.size_in_range(size) {
+                let hash = format!("inode_{}", representative.to_string_lossy());
+                let mut group =
+                    build_group(hash, size, std::slice::from_ref(representative), &roots);
+                group.kind = GroupKind::Hardlinks;
+                hardlink_groups.push(group);
+            }
+        }
+        for group in hardlink_groups {
+            confirm(group);
+        }
+    }
+
+    // サイズの大きい順にソート（通知した順序とほぼ同じだが、別枠のグループも含めて並べ直す）
+    let mut duplicate_groups = confirmed.into_inner().unwrap();
+    duplicate_groups.sort_by_key(|group| Reverse(group.size));
+
+    reporter.set_phase(ScanPhase::Done);
+
+    let skipped = reporter.take_skipped();
+    let mut skipped_counts = SkippedCounts::default();
+    for skipped_path in &skipped {
+        match skipped_path.reason {
+            SkipReason::PermissionDenied => skipped_counts.permission_denied += 1,
+            SkipReason::Vanished => skipped_counts.vanished += 1,
+            SkipReason::IoError => skipped_counts.io_error += 1,
+            SkipReason::ChangedDuringScan => skipped_counts.changed_during_scan += 1,
+        }
+    }
+
+    let (mut stats, timings) = reporter.totals();
+    stats.bytes_examined = bytes_examined;
+    for group in &duplicate_groups {
+        if group.kind == GroupKind::Duplicate {
+            stats.groups += 1;
+            stats.redundant_copies += group.redundant_copies();
+            stats.reclaimable_bytes += group.reclaimable_bytes();
+        }
+    }
+
+    Ok(ScanReport {
+        groups: duplicate_groups,
+        skipped,
+        skipped_counts,
+        stats,
+        timings,
+        options: options.clone(),
+    })
+}
+
+/// 同サイズの候補から、部分ハッシュとファイル全体のハッシュで内容が同一のファイル群を求める
+///   ステージ2.5: 先頭・中央・末尾の一部だけを xxh3 でハッシュし、明らかに内容の異なるファイルを除外
+///   ステージ3: 部分ハッシュが衝突したものだけを `options.hash_algorithm` で厳密に判定
+/// 確定したグループは、全体のハッシュ計算の完了を待たずにサイズの大きい順で `on_confirmed` に渡す
+/// ファイルは `store` の番号で扱い、パスは読み取るときと確定したグループについてだけ組み立てる
+fn find_identical_groups(
+    candidates: &[(u64, Vec<FileId>)],
+    store: &PathStore,
+    roots: &[ScanRoot],
+    options: &ScanOptions,
+    context: &ScanContext,
+    reporter: &ProgressReporter,
+    on_confirmed: &(dyn Fn(DuplicateGroup) + Sync),
+) -> Result<(), String> {
+    let threads = options.hash_threads();
+    let algorithm = options.hash_algorithm;
+    let (cache, cancel) = (context.cache, context.cancel);
+    let confirm = |size: u64, matched: &[(FileId, StampedDigest)]| {
+        // ハッシュの計算中・計算後に変更されたファイルは、報告するハッシュと内容が一致しないため外す
+        let stamped = matched.iter().map(|&(id, hash)| (id, Some(hash.stamp)));
+        let files = unchanged_files(stamped, store, size, reporter);
+        if files.len() < 2 {
+            return;
+        }
+        // ハッシュの衝突に備え、内容が異なるファイルがあればグループを分ける
+        let subsets = if options.verify_contents {
+            reporter.add_bytes_to_hash(size * files.len() as u64);
+            split_by_content(&files, cancel, reporter)
+        } else {
+            vec![files]
+        };
+        let hash = algorithm.to_hex(&matched[0].1.digest);
+        for files in subsets {
+            let mut group = build_group(hash.clone(), size, &files, roots);
+            group.hash_algorithm = Some(algorithm);
+            group.verified = options.verify_contents;
+            on_confirmed(group);
+        }
+    };
+    // 大きいファイルから順に全サイズグループの候補をまとめてワーカーへ渡す
+    reporter.set_hash_totals(
+        candidates.iter().map(|(_, ids)| ids.len() as u64).sum(),
+        candidates
+            .iter()
+            .map(|(size, ids)| partial_hash_len(*size) * ids.len() as u64)
+            .sum(),
+    );
+    reporter.set_phase(ScanPhase::Prefiltering);
+
+    // 部分ハッシュが衝突したファイル群のみを全体ハッシュの候補とする
+    // 候補はサイズの大きい順のため、部分ハッシュでファイル全体を読む小さいファイルは末尾にまとまっている
+    let (large, small) = candidates
+        .split_at(candidates.partition_point(|(size, _)| *size > PARTIAL_HASH_FULL_LIMIT));
+    let partial_groups: Mutex<Vec<(u64, Vec<FileId>)>> = Mutex::new(Vec::new());
+    hash_files_parallel(
+        store,
+        large,
+        threads,
+        cancel,
+        reporter,
+        |_, fp, size| calculate_partial_hash(fp, size, cancel, reporter),
+        |bucket, hashes| {
+            let (size, ids) = &large[bucket];
+            for matched in group_by_hash(ids, hashes.into_iter(), |&hash| hash) {
+                let matched_files = matched.iter().map(|&(id, _)| id).collect();
+                partial_groups.lock().unwrap().push((*size, matched_files));
+            }
+        },
+    )?;
+    let partial_groups = partial_groups.into_inner().unwrap();
+
+    // 小さいファイルは部分ハッシュの段階で `algorithm` で全体を読み、そのまま全体ハッシュとして使う
+    type SmallGroup = (u64, Vec<(FileId, StampedDigest)>);
+    let small_groups: Mutex<Vec<SmallGroup>> = Mutex::new(Vec::new());
+    hash_files_parallel(
+        store,
+        small,
+        threads,
+        cancel,
+        reporter,
+        |_, fp, _| hash_stamped(fp, || calculate_hash(fp, algorithm, cancel, reporter)),
+        |bucket, hashes| {
+            let (size, ids) = &small[bucket];
+            for matched in group_by_hash(ids, hashes.into_iter(), |hash| hash.digest) {
+                small_groups.lock().unwrap().push((*size, matched));
+            }
+        },
+    )?;
+
+    reporter.set_hash_totals(
+        partial_groups.iter().map(|(_, ids)| ids.len() as u64).sum(),
+        partial_groups
+            .iter()
+            .map(|(size, ids)| size * ids.len() as u64)
+            .sum(),
+    );
+    reporter.set_phase(ScanPhase::Hashing);
+
+    hash_files_parallel(
+        store,
+        &partial_groups,
+        threads,
+        cancel,
+        reporter,
+        |_, fp, _| {
+            hash_stamped(fp, || {
+                calculate_hash_cached(fp, algorithm, cache, cancel, reporter)
+            })
+        },
+        |bucket, hashes| {
+            // ハッシュが同一のファイルが2つ以上あるグループを重複として確定する
+            let (size, ids) = &partial_groups[bucket];
+            for matched in group_by_hash(ids, hashes.into_iter(), |hash| hash.digest) {
+                confirm(*size, &matched);
+            }
+        },
+    )?;
+
+    // 部分ハッシュで確定したグループは全体ハッシュの対象より小さいため最後に渡す
+    for (size, matched) in small_groups.into_inner().unwrap() {
+        confirm(size, &matched);
+    }
+    Ok(())
+}
+
+/// ファイル全体のハッシュと、計算する直前のファイルの状態
+/// 状態は確定時に変更されていないかを確かめるためだけに使うため、ファイルごとに保持する量を抑えて指紋で持つ
+#[derive(Clone, Copy)]
+struct StampedDigest {
+    digest: Digest,
+    stamp: u64,
+}
 
-            // ハッシュが同一のファイルが2つ以上あるグループを重複として登録
-            for (hash, matched_files) in hash_groups {
-                if matched_files.len() >= 2 {
-                    let file_infos: Vec<FileInfo> = matched_files
-                        .iter()
-                        .map(|fp| {
-                            let name = fp.file_name().unwrap_or_default().to_string_lossy().to_string();
-                            let extension = fp.extension().unwrap_or_default().to_string_lossy().to_string();
-                            FileInfo {
-                                path: fp.to_string_lossy().to_string(),
-                                name,
-                                size,
-                                hash: hash.clone(),
-                                extension,
-                            }
-                        })
-                        .collect();
-
-                    duplicate_groups.push(DuplicateGroup {
-                        hash: hash.clone(),
-                        size,
-                        files: file_infos,
-                    });
+/// ファイルの状態の指紋（更新日時を取得できない場合は 0 とし、サイズのみで比べる）
+fn stamp_fingerprint(metadata: &fs::Metadata) -> u64 {
+    FileStamp::from_metadata(metadata).map_or(0, |stamp| {
+        let mut hasher = DefaultHasher::new();
+        stamp.hash(&mut hasher);
+        hasher.finish()
+    })
+}
+
+/// ファイルの状態を記録してから、`hash` でファイル全体のハッシュを計算する
+fn hash_stamped(
+    path: &Path,
+    hash: impl FnOnce() -> io::Result<Digest>,
+) -> io::Result<StampedDigest> {
+    let stamp = stamp_fingerprint(&fs::metadata(path)?);
+    Ok(StampedDigest {
+        digest: hash()?,
+        stamp,
+    })
+}
+
+/// サイズが `size` のままで、記録した状態（指紋）から更新日時なども変わっていないファイルのパスだけを返す
+/// 変更されたファイルと消えたファイルはスキップとして記録する（状態を記録していないファイルはサイズのみ比べる）
+fn unchanged_files(
+    files: impl ExactSizeIterator<Item = (FileId, Option<u64>)>,
+    store: &PathStore,
+    size: u64,
+    reporter: &ProgressReporter,
+) -> Vec<PathBuf> {
+    let mut unchanged = Vec::with_capacity(files.len());
+    for (id, recorded) in files {
+        let path = store.path(id);
+        match fs::metadata(&path) {
+            Ok(metadata)
+                if metadata.len() == size
+                    && recorded.is_none_or(|stamp| stamp_fingerprint(&metadata) == stamp) =>
+            {
+                unchanged.push(path)
+            }
+            Ok(_) => reporter.path_changed(&path),
+            Err(e) => reporter.path_skipped(&path, &e),
+        }
+    }
+    unchanged
+}
+
+/// ハッシュを計算できなかったファイル（収集時のサイズは `size`）をスキップとして記録する
+/// 読み取り中に消えた・切り詰められたファイルは、読み取りエラーではなく消失・変更として扱うため、状態を取り直して判定する
+fn report_read_failure(path: &Path, size: u64, error: &io::Error, reporter: &ProgressReporter) {
+    match fs::metadata(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => reporter.path_skipped(path, &e),
+        Ok(metadata) if metadata.len() != size || error.kind() == io::ErrorKind::UnexpectedEof => {
+            reporter.path_changed(path)
+        }
+        _ => reporter.path_skipped(path, error),
+    }
+}
+
+/// バイト単位の検証で一度に読み比べるブロックのサイズ（小さすぎると HDD でシークが増える）
+const VERIFY_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// バイト単位の検証で開いたままにするファイル数の上限
+/// これより多いグループはブロックごとに開き直し、開けるファイル数の上限（macOS は既定で256）を超えないようにする
+const VERIFY_MAX_OPEN_FILES: usize = 64;
+
+/// バイト単位の検証中のファイル
+struct VerifyFile<'a> {
+    path: &'a PathBuf,
+    /// 開いたままにしない場合は None で、次のブロックを読むときに開き直す
+    file: Option<fs::File>,
+}
+
+impl VerifyFile<'_> {
+    /// `offset` から1ブロック読む（`keep_open` でなければ読み終えたら閉じる）
+    fn read_block(&mut self, offset: u64, buffer: &mut [u8], keep_open: bool) -> io::Result<usize> {
+        let mut file = match self.file.take() {
+            Some(file) => file,
+            None => {
+                let mut file = fs::File::open(self.path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                file
+            }
+        };
+        let bytes_read = read_block(&mut file, buffer)?;
+        if keep_open {
+            self.file = Some(file);
+        }
+        Ok(bytes_read)
+    }
+}
+
+/// 読んだブロックの内容と、同じ内容だったファイル
+type SameBlock<'a> = (Vec<u8>, Vec<VerifyFile<'a>>);
+
+/// 全ファイルを先頭から1ブロックずつ読み比べ、内容が完全に一致するもの同士に分ける
+/// 2つ以上一致したものだけをパス順で返す（読み取れなかったファイルはスキップとして記録する）
+fn split_by_content(
+    files: &[PathBuf],
+    cancel: &AtomicBool,
+    reporter: &ProgressReporter,
+) -> Vec<Vec<PathBuf>> {
+    let keep_open = files.len() <= VERIFY_MAX_OPEN_FILES;
+    let opened: Vec<VerifyFile> = files
+        .iter()
+        .map(|path| VerifyFile { path, file: None })
+        .collect();
+
+    // ここまでの内容が一致しているファイルの組と読んだ位置を、末尾に達するまで読み進める
+    let mut pending = vec![(0u64, opened)];
+    let mut identical: Vec<Vec<PathBuf>> = Vec::new();
+    let mut buffer = vec![0u8; VERIFY_BLOCK_SIZE];
+    while let Some((offset, subset)) = pending.pop() {
+        if subset.len() < 2 {
+            continue;
+        }
+        if cancel.load(Ordering::Relaxed) {
+            return Vec::new();
+        }
+        // ブロックの xxh3 で振り分け、同じ値のものは内容そのものを比べる（衝突しても取り違えない）
+        let mut by_block: HashMap<u128, Vec<SameBlock>> = HashMap::new();
+        for mut file in subset {
+            reporter.set_current_path(file.path);
+            let bytes_read = match file.read_block(offset, &mut buffer, keep_open) {
+                Ok(bytes_read) => bytes_read,
+                Err(e) => {
+                    reporter.path_skipped(file.path, &e);
+                    continue;
                 }
+            };
+            reporter.bytes_hashed(bytes_read as u64);
+            let block = &buffer[..bytes_read];
+            let same_hash = by_block.entry(xxh3_128(block)).or_default();
+            match same_hash.iter_mut().find(|(b, _)| b == block) {
+                Some((_, members)) => members.push(file),
+                None => same_hash.push((block.to_vec(), vec![file])),
             }
         }
+        for (block, members) in by_block.into_values().flatten() {
+            if block.is_empty() {
+                // 同時に末尾に達した（最後まで一致した）
+                identical.push(members.into_iter().map(|f| f.path.clone()).collect());
+            } else {
+                pending.push((offset + block.len() as u64, members));
+            }
+        }
+    }
+
+    for paths in &mut identical {
+        paths.sort();
+    }
+    identical.retain(|paths| paths.len() >= 2);
+    identical.sort();
+    identical
+}
+
+/// バッファが埋まるか末尾に達するまで読む（ファイル間でブロックの区切りを揃えるため）
+fn read_block(file: &mut fs::File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match file.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
     }
+    Ok(filled)
+}
 
-    // サイズの大きい順にソート
-    duplicate_groups.sort_by(|a, b| b.size.cmp(&a.size));
+/// ハードリンクとシンボリックリンクはコピーとしてではなく、同じファイルの別名として報告する
+fn annotate_links(
+    group: &mut DuplicateGroup,
+    hardlinks: &BTreeMap<PathBuf, Vec<PathBuf>>,
+    symlinks: &HashMap<PathBuf, Vec<PathBuf>>,
+) {
+    let to_strings = |links: &Vec<PathBuf>| -> Vec<String> {
+        links
+            .iter()
+            .map(|l| l.to_string_lossy().to_string())
+            .collect()
+    };
+    for file in &mut group.files {
+        let path = Path::new(&file.path);
+        if let Ok(metadata) = fs::metadata(path) {
+            (file.dev, file.ino) = device_and_inode(&metadata);
+        }
+        if let Some(links) = hardlinks.get(path) {
+            file.hardlinks = to_strings(links);
+        }
+        if let Some(links) = symlinks.get(path) {
+            file.symlinks = to_strings(links);
+        }
+    }
+}
 
-    Ok(duplicate_groups)
+/// `key(ハッシュ)` が同一のファイルをまとめ、2つ以上あるものだけを返す（ハッシュ順、グループ内は `ids` の順）
+/// `hashes` は `ids` と同じ順序で並んでいる必要がある
+/// 一意なファイルごとにグループを作らないよう、(番号, ハッシュ) の組を並べ替えて連続する区間を取り出す
+fn group_by_hash<H: Copy, K: Ord>(
+    ids: &[FileId],
+    hashes: impl Iterator<Item = Option<H>>,
+    key: impl Fn(&H) -> K,
+) -> Vec<Vec<(FileId, H)>> {
+    // ハッシュ計算に失敗したファイルはスキップ
+    let mut hashed: Vec<(FileId, H)> = ids
+        .iter()
+        .zip(hashes)
+        .filter_map(|(&id, hash)| Some((id, hash?)))
+        .collect();
+    // 安定ソートのため、同じハッシュのファイルは `ids` の順のまま並ぶ
+    hashed.sort_by_key(|(_, hash)| key(hash));
+    hashed
+        .chunk_by(|a, b| key(&a.1) == key(&b.1))
+        .filter(|run| run.len() >= 2)
+        .map(<[_]>::to_vec)
+        .collect()
 }
 
-/// ファイルのSHA-256ハッシュを計算
-fn calculate_hash(path: &Path) -> Result<String, String> {
-    let mut file = fs::File::open(path).map_err(|e| format!("ファイルを開けません: {}", e))?;
-    let mut hasher = Sha256::new();
-    let mut buffer = [0u8; 8192];
+/// パスの一覧から重複グループを組み立てる
+fn build_group(hash: String, size: u64, files: &[PathBuf], roots: &[ScanRoot]) -> DuplicateGroup {
+    let file_infos: Vec<FileInfo> = files
+        .iter()
+        .map(|fp| {
+            let name = fp
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            let extension = fp
+                .extension()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            let root = root_of(fp, roots);
+            FileInfo {
+                path: fp.to_string_lossy().to_string(),
+                name,
+                size,
+                hash: hash.clone(),
+                extension,
+                root: root
+                    .map(|root| root.path.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                is_reference: root.is_none_or(|root| root.reference),
+                symlinks: Vec::new(),
+                dev: 0,
+                ino: 0,
+                hardlinks: Vec::new(),
+            }
+        })
+        .collect();
+
+    DuplicateGroup {
+        hash,
+        size,
+        files: file_infos,
+        kind: GroupKind::Duplicate,
+        hash_algorithm: None,
+        verified: false,
+    }
+}
+
+/// ファイル名が一致したファイル群からグループを組み立てる
+/// サイズはファイルごとに異なりうるため、グループのサイズには最大のものを使う
+fn build_name_group(key: &str, files: &[(PathBuf, u64)], roots: &[ScanRoot]) -> DuplicateGroup {
+    let paths: Vec<PathBuf> = files.iter().map(|(fp, _)| fp.clone()).collect();
+    let max_size = files.iter().map(|(_, size)| *size).max().unwrap_or(0);
+    let mut group = build_group(format!("name_{}", key), max_size, &paths, roots);
+    for (info, (_, size)) in group.files.iter_mut().zip(files) {
+        info.size = *size;
+    }
+    group
+}
+
+/// サイズごとにまとめたファイル（`store` の番号）のハッシュを `threads` 本のワーカーで並列に計算する
+/// パスはワーカーが読み取る直前に組み立て、`hash_file(番号, パス, サイズ)` に渡す
+/// `buckets` の1つ分のハッシュが揃うたびに、先頭から順に `on_bucket(番号, ハッシュ)` を呼ぶ
+/// ハッシュは `buckets` 内の番号と同じ順序で並ぶ（計算に失敗したファイルは None とし、スキップとして記録する）
+/// `on_bucket` は通知用のスレッドで呼ぶため、時間がかかってもワーカーのハッシュ計算は止まらない
+fn hash_files_parallel<H, F, B>(
+    store: &PathStore,
+    buckets: &[(u64, Vec<FileId>)],
+    threads: usize,
+    cancel: &AtomicBool,
+    reporter: &ProgressReporter,
+    hash_file: F,
+    on_bucket: B,
+) -> Result<(), String>
+where
+    H: Copy + Send,
+    F: Fn(FileId, &Path, u64) -> io::Result<H> + Sync,
+    B: Fn(usize, Vec<Option<H>>) + Sync,
+{
+    struct Results<H> {
+        hashes: Vec<Option<H>>,
+        /// 区間ごとの未計算のファイル数
+        remaining: Vec<usize>,
+        next_bucket: usize,
+    }
+
+    let files: Vec<(FileId, u64)> = buckets
+        .iter()
+        .flat_map(|(size, ids)| ids.iter().map(move |&id| (id, *size)))
+        .collect();
+    // 各区間の先頭の位置（ファイルがどの区間に属するかを二分探索で求める）
+    let starts: Vec<usize> = buckets
+        .iter()
+        .scan(0, |start, (_, ids)| {
+            let bucket_start = *start;
+            *start += ids.len();
+            Some(bucket_start)
+        })
+        .collect();
+    let next = AtomicUsize::new(0);
+    let workers = threads.clamp(1, files.len().max(1));
+    let results = Mutex::new(Results {
+        hashes: vec![None; files.len()],
+        remaining: buckets.iter().map(|(_, ids)| ids.len()).collect(),
+        next_bucket: 0,
+    });
+
+    thread::scope(|scope| {
+        // 揃った区間はロックを持ったまま順に送り、通知用のスレッドで受け取った順に通知する
+        let (ready_tx, ready_rx) = mpsc::channel::<(usize, Vec<Option<H>>)>();
+        let on_bucket = &on_bucket;
+        scope.spawn(move || {
+            for (bucket, hashes) in ready_rx {
+                on_bucket(bucket, hashes);
+            }
+        });
+        for _ in 0..workers {
+            let ready_tx = ready_tx.clone();
+            let (files, starts, next, results, hash_file) =
+                (&files, &starts, &next, &results, &hash_file);
+            scope.spawn(move || {
+                while !cancel.load(Ordering::Relaxed) {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(&(id, size)) = files.get(index) else {
+                        break;
+                    };
+                    let path = store.path(id);
+                    reporter.set_current_path(&path);
+                    let hash = match hash_file(id, &path, size) {
+                        Ok(hash) => Some(hash),
+                        // キャンセルによる中断は下でまとめて扱う
+                        Err(_) if cancel.load(Ordering::Relaxed) => break,
+                        Err(e) => {
+                            report_read_failure(&path, size, &e, reporter);
+                            None
+                        }
+                    };
+                    reporter.file_hashed();
+
+                    let mut results = results.lock().unwrap();
+                    results.hashes[index] = hash;
+                    let bucket = starts.partition_point(|&start| start <= index) - 1;
+                    results.remaining[bucket] -= 1;
+                    while results.remaining.get(results.next_bucket) == Some(&0) {
+                        let bucket = results.next_bucket;
+                        let range = starts[bucket]..starts[bucket] + buckets[bucket].1.len();
+                        // 通知した区間のハッシュは以降使わないため、複製せずに取り出す
+                        let bucket_hashes: Vec<Option<H>> =
+                            results.hashes[range].iter_mut().map(Option::take).collect();
+                        let _ = ready_tx.send((bucket, bucket_hashes));
+                        results.next_bucket += 1;
+                    }
+                }
+            });
+        }
+    });
+
+    // キャンセルによる中断はスキップ扱いにせずスキャン全体を終了する
+    check_cancelled(cancel)
+}
+
+/// ハッシュ計算時の読み取りバッファサイズ
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+/// 部分ハッシュで読み取るブロックのサイズ（先頭・中央・末尾のそれぞれ）
+const PARTIAL_HASH_BLOCK: u64 = 16 * 1024;
+
+/// このサイズ以下のファイルは部分ハッシュでファイル全体を読む
+const PARTIAL_HASH_FULL_LIMIT: u64 = PARTIAL_HASH_BLOCK * 3;
+
+/// 部分ハッシュで読み取るバイト数
+fn partial_hash_len(size: u64) -> u64 {
+    if size <= PARTIAL_HASH_FULL_LIMIT {
+        size
+    } else {
+        PARTIAL_HASH_BLOCK * 3
+    }
+}
+
+/// ファイルの先頭・中央・末尾のブロックのみから xxh3 を計算する（全体ハッシュ前の絞り込み用）
+/// `PARTIAL_HASH_FULL_LIMIT` より大きいファイルのみが対象
+fn calculate_partial_hash(
+    path: &Path,
+    size: u64,
+    cancel: &AtomicBool,
+    reporter: &ProgressReporter,
+) -> io::Result<u128> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Xxh3::new();
+    let mut buffer = vec![0u8; PARTIAL_HASH_BLOCK as usize];
+    let offsets = [
+        0,
+        size / 2 - PARTIAL_HASH_BLOCK / 2,
+        size - PARTIAL_HASH_BLOCK,
+    ];
+
+    for offset in offsets {
+        check_cancelled_io(cancel)?;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut buffer)?;
+        hasher.update(&buffer);
+        reporter.bytes_hashed(PARTIAL_HASH_BLOCK);
+    }
+
+    Ok(hasher.digest128())
+}
+
+/// キャッシュにハッシュがあれば再利用し、なければ計算してキャッシュへ登録する
+fn calculate_hash_cached(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    cache: Option<&Mutex<HashCache>>,
+    cancel: &AtomicBool,
+    reporter: &ProgressReporter,
+) -> io::Result<Digest> {
+    let Some(cache) = cache else {
+        return calculate_hash(path, algorithm, cancel, reporter);
+    };
+    let stamp = fs::metadata(path)
+        .ok()
+        .and_then(|metadata| FileStamp::from_metadata(&metadata));
+
+    if let Some(stamp) = &stamp {
+        if let Some(digest) = cache.lock().unwrap().get(path, stamp, algorithm) {
+            reporter.bytes_from_cache(stamp.size);
+            return Ok(digest);
+        }
+    }
+
+    let digest = calculate_hash(path, algorithm, cancel, reporter)?;
+    if let Some(stamp) = stamp {
+        cache.lock().unwrap().insert(path, stamp, algorithm, digest);
+    }
+    Ok(digest)
+}
+
+/// ファイル全体のハッシュを `algorithm` で計算
+/// 読み取ったバイト数は随時 `reporter` に加算される
+fn calculate_hash(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    cancel: &AtomicBool,
+    reporter: &ProgressReporter,
+) -> io::Result<Digest> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = algorithm.hasher();
+    let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
 
     loop {
-        let bytes_read = file
-            .read(&mut buffer)
-            .map_err(|e| format!("ファイル読み取りエラー: {}", e))?;
+        check_cancelled_io(cancel)?;
+        let bytes_read = file.read(&mut buffer)?;
         if bytes_read == 0 {
             break;
         }
         hasher.update(&buffer[..bytes_read]);
+        reporter.bytes_hashed(bytes_read as u64);
     }
 
-    let result = hasher.finalize();
-    Ok(format!("{:x}", result))
+    Ok(hasher.finish())
 }
 
 /// ファイルのプレビューデータを取得
@@ -227,7 +1611,11 @@ pub fn get_preview(file_path: &str) -> Result<FilePreview, String> {
 }
 
 /// ファイルをゴミ箱に移動して削除
-pub fn delete_files_to_trash(file_paths: &[String]) -> Result<DeleteResult, String> {
+/// `protected_roots`（正規化済みの参照フォルダ）配下のファイルは削除せず失敗として返す
+pub fn delete_files_to_trash(
+    file_paths: &[String],
+    protected_roots: &[PathBuf],
+) -> Result<DeleteResult, String> {
     let mut deleted = Vec::new();
     let mut failed = Vec::new();
 
@@ -241,6 +1629,18 @@ pub fn delete_files_to_trash(file_paths: &[String]) -> Result<DeleteResult, Stri
             continue;
         }
 
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if protected_roots
+            .iter()
+            .any(|root| canonical.starts_with(root))
+        {
+            failed.push(DeleteError {
+                path: path_str.clone(),
+                error: "参照フォルダ内のファイルは削除できません".to_string(),
+            });
+            continue;
+        }
+
         match trash::delete(path) {
             Ok(_) => deleted.push(path_str.clone()),
             Err(e) => {
@@ -281,6 +1681,17 @@ mod tests {
     use std::io::Write;
     use std::path::PathBuf;
 
+    /// キャンセル・キャッシュ・通知を使わずにスキャンする
+    fn scan(options: &ScanOptions) -> Result<ScanReport, String> {
+        let context = ScanContext {
+            cache: None,
+            cancel: &AtomicBool::new(false),
+            on_progress: &|_| {},
+            on_group: &|_| {},
+        };
+        scan_for_duplicates(options, &context)
+    }
+
     #[test]
     fn test_duplicate_detection() {
         // テスト用のディレクトリを作成
@@ -312,8 +1723,14 @@ mod tests {
         let mut f5 = File::create(&file5).unwrap();
         f5.write_all(b"Size identical, but...B").unwrap(); // 23 bytes
 
-        // スキャン実行 (strict mode, recursive false)
-        let groups = scan_for_duplicates(test_dir, "strict", false).unwrap();
+        // スキャン実行 (strict mode)
+        let groups = scan(&ScanOptions {
+            paths: vec![test_dir.to_string()],
+            threads: Some(1),
+            ..Default::default()
+        })
+        .unwrap()
+        .groups;
 
         // クリーンアップ
         let _ = fs::remove_dir_all(test_dir);
@@ -334,4 +1751,946 @@ mod tests {
         assert!(paths.contains(&file2.to_string_lossy().to_string()));
         assert!(!paths.contains(&file4.to_string_lossy().to_string()));
     }
+
+    #[test]
+    fn test_multiple_roots() {
+        let test_dir = "test_multiple_roots_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        let pictures = PathBuf::from(test_dir).join("pictures");
+        let external = PathBuf::from(test_dir).join("external");
+        let nested = pictures.join("nested");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(&external).unwrap();
+        for file_path in [
+            pictures.join("a.jpg"),
+            external.join("a_backup.jpg"),
+            nested.join("a2.jpg"),
+        ] {
+            File::create(file_path)
+                .unwrap()
+                .write_all(b"same photo")
+                .unwrap();
+        }
+
+        let roots: Vec<String> = [&pictures, &external, &nested]
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        let groups = scan(&ScanOptions {
+            paths: roots.clone(),
+            threads: Some(1),
+            ..Default::default()
+        });
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        // 入れ子の nested は pictures の走査に含まれるため、同じファイルが二重に数えられない
+        let groups = groups.unwrap().groups;
+        assert_eq!(groups.len(), 1);
+        let mut found: Vec<(String, String)> = groups[0]
+            .files
+            .iter()
+            .map(|f| (f.name.clone(), f.root.clone()))
+            .collect();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                ("a.jpg".to_string(), roots[0].clone()),
+                ("a2.jpg".to_string(), roots[0].clone()),
+                ("a_backup.jpg".to_string(), roots[1].clone()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reference_roots() {
+        let test_dir = "test_reference_roots_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        let archive = PathBuf::from(test_dir).join("archive");
+        let downloads = PathBuf::from(test_dir).join("downloads");
+        fs::create_dir_all(&archive).unwrap();
+        fs::create_dir_all(&downloads).unwrap();
+        let write =
+            |path: PathBuf, data: &[u8]| File::create(path).unwrap().write_all(data).unwrap();
+        // アーカイブ済みのファイルのコピー
+        write(archive.join("report.pdf"), b"archived report");
+        write(downloads.join("report.pdf"), b"archived report");
+        // アーカイブ内だけの重複（報告しない）
+        write(archive.join("old1.txt"), b"archive only dup");
+        write(archive.join("old2.txt"), b"archive only dup");
+
+        let targets = vec![downloads.to_string_lossy().to_string()];
+        let references = vec![archive.to_string_lossy().to_string()];
+        let groups = scan(&ScanOptions {
+            paths: targets.clone(),
+            reference_paths: references.clone(),
+            threads: Some(1),
+            ..Default::default()
+        });
+
+        // 参照フォルダ内のファイルは削除を拒否する
+        let protected = vec![fs::canonicalize(&archive).unwrap()];
+        let archived_path = archive.join("report.pdf").to_string_lossy().to_string();
+        let delete_result =
+            delete_files_to_trash(std::slice::from_ref(&archived_path), &protected).unwrap();
+        let still_exists = archive.join("report.pdf").exists();
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        let groups = groups.unwrap().groups;
+        assert_eq!(groups.len(), 1);
+        let flags: Vec<(String, bool)> = groups[0]
+            .files
+            .iter()
+            .map(|f| (f.root.clone(), f.is_reference))
+            .collect();
+        assert_eq!(
+            flags,
+            vec![(references[0].clone(), true), (targets[0].clone(), false)]
+        );
+        assert!(delete_result.deleted.is_empty());
+        assert_eq!(delete_result.failed[0].path, archived_path);
+        assert!(still_exists);
+    }
+
+    #[test]
+    fn test_name_stage() {
+        let test_dir = "test_name_stage_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        let sub = PathBuf::from(test_dir).join("sub");
+        fs::create_dir_all(&sub).unwrap();
+        let write =
+            |path: PathBuf, data: &[u8]| File::create(path).unwrap().write_all(data).unwrap();
+        write(PathBuf::from(test_dir).join("photo.jpg"), b"same content");
+        write(sub.join("photo (1).jpg"), b"same content");
+        // 内容は同じだが名前が無関係
+        write(sub.join("unrelated.jpg"), b"same content");
+        // 名前は同じだが内容が異なる
+        write(PathBuf::from(test_dir).join("notes.txt"), b"version 1");
+        write(sub.join("notes.txt"), b"version two");
+
+        let roots = vec![test_dir.to_string()];
+        let options = |mode: ScanMode, name_match: NameMatch| ScanOptions {
+            paths: roots.clone(),
+            mode,
+            name_match,
+            threads: Some(1),
+            ..Default::default()
+        };
+        let names = |groups: &[DuplicateGroup]| -> Vec<Vec<String>> {
+            groups
+                .iter()
+                .map(|g| g.files.iter().map(|f| f.name.clone()).collect())
+                .collect()
+        };
+        let name_only = scan(&options(ScanMode::NameOnly, NameMatch::Exact))
+            .unwrap()
+            .groups;
+        let name_and_hash = scan(&options(ScanMode::Strict, NameMatch::IgnoreCopySuffix))
+            .unwrap()
+            .groups;
+        let missing_name_match = scan(&options(ScanMode::NameOnly, NameMatch::Off));
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        // 名前のみ: 内容が異なっても同名なら同じグループ
+        assert_eq!(names(&name_only), vec![vec!["notes.txt", "notes.txt"]]);
+        assert_eq!(name_only[0].files[1].size, 11);
+        // 名前 + ハッシュ: 複製時の連番を無視し、内容も一致するものだけ
+        assert_eq!(
+            names(&name_and_hash),
+            vec![vec!["photo.jpg", "photo (1).jpg"]]
+        );
+        assert!(missing_name_match.is_err());
+    }
+
+    #[test]
+    fn test_filters_prune_directories() {
+        let test_dir = "test_filters_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        let modules = PathBuf::from(test_dir).join("app").join("node_modules");
+        fs::create_dir_all(&modules).unwrap();
+        let write =
+            |path: PathBuf, data: &[u8]| File::create(path).unwrap().write_all(data).unwrap();
+        write(modules.join("index.js"), b"module.exports = 1;");
+        write(
+            PathBuf::from(test_dir).join("app").join("index.js"),
+            b"module.exports = 1;",
+        );
+        write(PathBuf::from(test_dir).join("a.txt"), b"text");
+        write(PathBuf::from(test_dir).join("b.txt"), b"text");
+
+        let options = |exclude_globs: Vec<&str>, extensions: Vec<&str>| ScanOptions {
+            paths: vec![test_dir.to_string()],
+            exclude_globs: exclude_globs.into_iter().map(String::from).collect(),
+            extensions: extensions.into_iter().map(String::from).collect(),
+            ..Default::default()
+        };
+        let unfiltered = scan(&options(vec![], vec![])).unwrap().groups;
+        let excluded = scan(&options(vec!["node_modules"], vec![])).unwrap().groups;
+        let only_js = scan(&options(vec![], vec![".js"])).unwrap().groups;
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        assert_eq!(unfiltered.len(), 2);
+        assert_eq!(excluded.len(), 1);
+        assert_eq!(excluded[0].files[0].name, "a.txt");
+        assert_eq!(only_js.len(), 1);
+        assert_eq!(only_js[0].files[0].name, "index.js");
+    }
+
+    #[test]
+    fn test_size_thresholds_and_empty_files() {
+        let test_dir = "test_size_thresholds_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+        let write = |name: &str, data: &[u8]| {
+            File::create(PathBuf::from(test_dir).join(name))
+                .unwrap()
+                .write_all(data)
+                .unwrap()
+        };
+        write("empty1.lock", b"");
+        write("empty2.lock", b"");
+        write("tiny1.txt", b"x");
+        write("tiny2.txt", b"x");
+        write("large1.bin", b"0123456789");
+        write("large2.bin", b"0123456789");
+
+        let options = |min_size: Option<u64>, report_empty_files: bool| ScanOptions {
+            paths: vec![test_dir.to_string()],
+            min_size,
+            report_empty_files,
+            ..Default::default()
+        };
+        let default = scan(&options(None, false)).unwrap().groups;
+        let thresholded = scan(&options(Some(2), true)).unwrap().groups;
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        // 空ファイルは既定では報告しない
+        let sizes: Vec<u64> = default.iter().map(|g| g.size).collect();
+        assert_eq!(sizes, vec![10, 1]);
+        // 最小サイズ未満は除外し、空ファイルは別枠で報告する
+        let kinds: Vec<(u64, GroupKind)> = thresholded.iter().map(|g| (g.size, g.kind)).collect();
+        assert_eq!(
+            kinds,
+            vec![(10, GroupKind::Duplicate), (0, GroupKind::EmptyFiles)]
+        );
+        assert_eq!(thresholded[1].files.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let test_dir = "test_symlinks_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        let dir = PathBuf::from(test_dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        File::create(dir.join("a.txt"))
+            .unwrap()
+            .write_all(b"same content")
+            .unwrap();
+        File::create(dir.join("sub/b.txt"))
+            .unwrap()
+            .write_all(b"same content")
+            .unwrap();
+        let absolute = fs::canonicalize(&dir).unwrap();
+        // 親フォルダへのリンク（循環）と、ファイルへのリンク
+        symlink(&absolute, dir.join("sub/loop")).unwrap();
+        symlink(absolute.join("a.txt"), dir.join("sub/link.txt")).unwrap();
+        // リンク先のないリンクと、自分自身を指して解決できないリンク
+        symlink(absolute.join("missing.txt"), dir.join("dangling.txt")).unwrap();
+        symlink(absolute.join("self.txt"), dir.join("self.txt")).unwrap();
+
+        let options = |follow_symlinks: bool| ScanOptions {
+            paths: vec![test_dir.to_string()],
+            follow_symlinks,
+            ..Default::default()
+        };
+        let followed = scan(&options(true)).unwrap();
+        let not_followed = scan(&options(false)).unwrap();
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        // 循環しても終了し、リンクはコピーとして数えずリンク先の別名として報告する
+        for report in [followed, not_followed] {
+            // 壊れたリンクは無視し、解決できないリンクはスキップとして記録する
+            assert_eq!(report.skipped.len(), 1);
+            assert!(report.skipped[0].path.ends_with("self.txt"));
+            assert_eq!(report.skipped[0].reason, SkipReason::IoError);
+            let result = report.groups;
+            assert_eq!(result.len(), 1);
+            assert_eq!(result[0].files.len(), 2);
+            let with_link = result[0].files.iter().find(|f| f.name == "a.txt").unwrap();
+            assert_eq!(with_link.symlinks.len(), 1);
+            assert!(with_link.symlinks[0].ends_with("link.txt"));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hardlinks_are_collapsed() {
+        let test_dir = "test_hardlinks_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        let dir = PathBuf::from(test_dir);
+        fs::create_dir(&dir).unwrap();
+        File::create(dir.join("a.txt"))
+            .unwrap()
+            .write_all(b"same content")
+            .unwrap();
+        File::create(dir.join("b.txt"))
+            .unwrap()
+            .write_all(b"same content")
+            .unwrap();
+        File::create(dir.join("solo.txt"))
+            .unwrap()
+            .write_all(b"only linked")
+            .unwrap();
+        fs::hard_link(dir.join("a.txt"), dir.join("a_link.txt")).unwrap();
+        fs::hard_link(dir.join("solo.txt"), dir.join("solo_link.txt")).unwrap();
+
+        let options = ScanOptions {
+            paths: vec![test_dir.to_string()],
+            report_hardlinks: true,
+            ..Default::default()
+        };
+        let result = scan(&options).unwrap().groups;
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        assert_eq!(result.len(), 2);
+        // ハードリンクは1つのファイルにまとめられ、他のパスとして表示される
+        let duplicates = &result[0];
+        assert_eq!(duplicates.kind, GroupKind::Duplicate);
+        assert_eq!(duplicates.files.len(), 2);
+        let linked = duplicates.files.iter().find(|f| f.name == "a.txt").unwrap();
+        assert_eq!(linked.hardlinks.len(), 1);
+        assert!(linked.hardlinks[0].ends_with("a_link.txt"));
+        assert_ne!(linked.ino, 0);
+        // 実体が1つしかないものは重複ではなくハードリンクとして別枠で報告する
+        let solo = &result[1];
+        assert_eq!(solo.kind, GroupKind::Hardlinks);
+        assert_eq!(solo.files.len(), 1);
+        assert_eq!(solo.files[0].hardlinks.len(), 1);
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let test_dir = "test_max_depth_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        let dir = PathBuf::from(test_dir);
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        for file in ["top.txt", "a/one.txt", "a/b/two.txt"] {
+            File::create(dir.join(file))
+                .unwrap()
+                .write_all(b"same content")
+                .unwrap();
+        }
+
+        let count = |max_depth: Option<usize>| {
+            let options = ScanOptions {
+                paths: vec![test_dir.to_string()],
+                max_depth,
+                ..Default::default()
+            };
+            let groups = scan(&options).unwrap().groups;
+            groups.first().map_or(0, |g| g.files.len())
+        };
+        let counts = [count(Some(0)), count(Some(1)), count(Some(2)), count(None)];
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        // 直下のみでは1件しかないため重複なし
+        assert_eq!(counts, [0, 2, 3, 3]);
+    }
+
+    #[test]
+    fn test_unreadable_files_are_reported() {
+        let test_dir = "test_skipped_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+        let present = PathBuf::from(test_dir).join("present.txt");
+        File::create(&present).unwrap().write_all(b"data").unwrap();
+        let vanished = PathBuf::from(test_dir).join("vanished.txt");
+        // 収集後に切り詰められたファイル（部分ハッシュの読み取りが途中で終わる）
+        let truncated = PathBuf::from(test_dir).join("truncated.bin");
+        File::create(&truncated)
+            .unwrap()
+            .write_all(b"data")
+            .unwrap();
+
+        let cancel = AtomicBool::new(false);
+        let reporter = ProgressReporter::new(&|_| {});
+        let mut store = PathStore::new();
+        let buckets = [
+            (PARTIAL_HASH_FULL_LIMIT * 2, vec![store.push(&truncated)]),
+            (4, vec![store.push(&present), store.push(&vanished)]),
+        ];
+        let hashes = Mutex::new(Vec::new());
+        hash_files_parallel(
+            &store,
+            &buckets,
+            1,
+            &cancel,
+            &reporter,
+            |_, fp, size| {
+                if size > PARTIAL_HASH_FULL_LIMIT {
+                    calculate_partial_hash(fp, size, &cancel, &reporter).map(|_| ())
+                } else {
+                    calculate_hash(fp, HashAlgorithm::Sha256, &cancel, &reporter).map(|_| ())
+                }
+            },
+            |_, bucket_hashes| hashes.lock().unwrap().extend(bucket_hashes),
+        )
+        .unwrap();
+        let hashes = hashes.into_inner().unwrap();
+        let skipped = reporter.take_skipped();
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        // 読み取れなかったファイルは黙って捨てず、理由とともに記録する
+        assert_eq!(hashes, vec![None, Some(()), None]);
+        assert_eq!(skipped.len(), 2);
+        // 読み取り中に切り詰められたファイルは読み取りエラーではなく変更として扱う
+        assert_eq!(skipped[0].reason, SkipReason::ChangedDuringScan);
+        assert!(skipped[0].path.ends_with("truncated.bin"));
+        assert_eq!(skipped[1].reason, SkipReason::Vanished);
+        assert!(skipped[1].path.ends_with("vanished.txt"));
+    }
+
+    #[test]
+    fn test_scan_report_stats() {
+        let test_dir = "test_report_stats_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        let dir = PathBuf::from(test_dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        for (file, content) in [
+            ("a.txt", "twelve bytes"),
+            ("b.txt", "twelve bytes"),
+            ("sub/c.txt", "twelve bytes"),
+            ("sub/unique.txt", "different"),
+        ] {
+            File::create(dir.join(file))
+                .unwrap()
+                .write_all(content.as_bytes())
+                .unwrap();
+        }
+
+        let options = ScanOptions {
+            paths: vec![test_dir.to_string()],
+            threads: Some(1),
+            ..Default::default()
+        };
+        let report = scan(&options).unwrap();
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        assert_eq!(
+            report.stats,
+            ScanStats {
+                files_walked: 4,
+                dirs_walked: 2,
+                bytes_examined: 12 * 3 + 9,
+                // 同サイズの3ファイルのみ読む（小さいファイルは部分ハッシュが全体ハッシュを兼ねる）
+                bytes_hashed: 12 * 3,
+                bytes_from_cache: 0,
+                groups: 1,
+                redundant_copies: 2,
+                reclaimable_bytes: 24,
+            }
+        );
+        assert_eq!(report.options.paths, options.paths);
+        assert!(report.timings.total_ms >= report.timings.hashing_ms);
+    }
+
+    #[test]
+    fn test_cancelled_scan() {
+        let test_dir = "test_cancelled_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+        File::create(PathBuf::from(test_dir).join("a.txt"))
+            .unwrap()
+            .write_all(b"same")
+            .unwrap();
+        File::create(PathBuf::from(test_dir).join("b.txt"))
+            .unwrap()
+            .write_all(b"same")
+            .unwrap();
+
+        let cancel = AtomicBool::new(true);
+        let result = scan_for_duplicates(
+            &ScanOptions {
+                paths: vec![test_dir.to_string()],
+                threads: Some(1),
+                ..Default::default()
+            },
+            &ScanContext {
+                cache: None,
+                cancel: &cancel,
+                on_progress: &|_| {},
+                on_group: &|_| {},
+            },
+        );
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        assert_eq!(result.unwrap_err(), CANCELLED_MESSAGE);
+    }
+
+    #[test]
+    fn test_parallel_hashing_is_deterministic() {
+        let test_dir = "test_parallel_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+        // 同サイズ・異なる内容のファイルを複数作り、同じサイズ内に複数グループができるようにする
+        for i in 0..6 {
+            for copy in 0..3 {
+                let path = PathBuf::from(test_dir).join(format!("file{}_{}.bin", i, copy));
+                let content = format!("content-{}", i);
+                File::create(path)
+                    .unwrap()
+                    .write_all(content.as_bytes())
+                    .unwrap();
+            }
+        }
+
+        let sequential = scan(&ScanOptions {
+            paths: vec![test_dir.to_string()],
+            threads: Some(1),
+            ..Default::default()
+        });
+        let parallel = scan(&ScanOptions {
+            paths: vec![test_dir.to_string()],
+            threads: Some(4),
+            ..Default::default()
+        });
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        let summarize = |groups: Vec<DuplicateGroup>| -> Vec<(String, Vec<String>)> {
+            groups
+                .into_iter()
+                .map(|g| (g.hash, g.files.into_iter().map(|f| f.path).collect()))
+                .collect()
+        };
+        let sequential = summarize(sequential.unwrap().groups);
+        assert_eq!(sequential.len(), 6);
+        assert!(sequential.iter().all(|(_, files)| files.len() == 3));
+        assert_eq!(sequential, summarize(parallel.unwrap().groups));
+    }
+
+    #[test]
+    fn test_verify_contents() {
+        let test_dir = "test_verify_contents_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+
+        // ブロックの境界をまたぎ、最後のバイトだけが異なる
+        let base = vec![b'v'; VERIFY_BLOCK_SIZE + 10];
+        let mut last_differs = base.clone();
+        *last_differs.last_mut().unwrap() = b'w';
+        let write = |name: &str, data: &[u8]| {
+            let path = PathBuf::from(test_dir).join(name);
+            File::create(&path).unwrap().write_all(data).unwrap();
+            path
+        };
+        let files = vec![
+            write("a.bin", &base),
+            write("b.bin", &last_differs),
+            write("c.bin", &base),
+            write("d.bin", &last_differs),
+            write("e.bin", &base[..VERIFY_BLOCK_SIZE]),
+        ];
+
+        // 開いたままにできる数より多いファイルは、ブロックごとに開き直して読み比べる
+        let mut many = files.clone();
+        for i in 0..VERIFY_MAX_OPEN_FILES {
+            many.push(write(&format!("many_{:02}.bin", i), &last_differs));
+        }
+
+        let cancel = AtomicBool::new(false);
+        let reporter = ProgressReporter::new(&|_| {});
+        let subsets = split_by_content(&files, &cancel, &reporter);
+        let many_subsets = split_by_content(&many, &cancel, &reporter);
+        let report = scan_for_duplicates(
+            &ScanOptions {
+                paths: vec![test_dir.to_string()],
+                verify_contents: true,
+                ..Default::default()
+            },
+            &ScanContext {
+                cache: None,
+                cancel: &cancel,
+                on_progress: &|_| {},
+                on_group: &|_| {},
+            },
+        );
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        assert_eq!(
+            subsets,
+            vec![
+                vec![files[0].clone(), files[2].clone()],
+                vec![files[1].clone(), files[3].clone()],
+            ]
+        );
+        assert_eq!(many_subsets.len(), 2);
+        assert_eq!(many_subsets[0], subsets[0]);
+        assert_eq!(many_subsets[1].len(), VERIFY_MAX_OPEN_FILES + 2);
+        let groups = report.unwrap().groups;
+        assert_eq!(groups.len(), 2);
+        assert!(groups.iter().all(|g| g.verified));
+    }
+
+    #[test]
+    fn test_files_changed_during_scan() {
+        let test_dir = "test_changed_during_scan_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+        let dir = PathBuf::from(test_dir);
+        let large = vec![b'l'; (PARTIAL_HASH_FULL_LIMIT * 2) as usize];
+        for name in ["large_a.bin", "large_b.bin"] {
+            fs::write(dir.join(name), &large).unwrap();
+        }
+        for name in ["small_a.txt", "small_b.txt", "small_c.txt"] {
+            fs::write(dir.join(name), b"small").unwrap();
+        }
+
+        // 小さいファイルのハッシュは先に計算済みのため、大きいファイルのグループの確定時に書き換える
+        let changed = dir.join("small_c.txt");
+        let report = scan_for_duplicates(
+            &ScanOptions {
+                paths: vec![test_dir.to_string()],
+                threads: Some(1),
+                ..Default::default()
+            },
+            &ScanContext {
+                cache: None,
+                cancel: &AtomicBool::new(false),
+                on_progress: &|_| {},
+                on_group: &|group| {
+                    if group.size > PARTIAL_HASH_FULL_LIMIT {
+                        fs::write(&changed, b"small, then edited").unwrap();
+                    }
+                },
+            },
+        );
+
+        // サイズが同じでも、更新日時が変われば変更とみなす
+        let mut store = PathStore::new();
+        let same_size = dir.join("small_a.txt");
+        let ids = [store.push(&same_size), store.push(&dir.join("small_b.txt"))];
+        let stamp = stamp_fingerprint(&fs::metadata(&same_size).unwrap());
+        File::options()
+            .write(true)
+            .open(&same_size)
+            .unwrap()
+            .set_modified(std::time::UNIX_EPOCH)
+            .unwrap();
+        let reporter = ProgressReporter::new(&|_| {});
+        // ハッシュの計算後に消えたファイルは変更ではなく消失として扱う
+        let removed = store.push(&dir.join("removed.txt"));
+        let recorded = [(ids[0], Some(stamp)), (ids[1], None), (removed, None)];
+        let unchanged = unchanged_files(recorded.into_iter(), &store, 5, &reporter);
+        let confirm_skipped = reporter.take_skipped();
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        let report = report.unwrap();
+        assert_eq!(report.groups.len(), 2);
+        let small_names: Vec<&str> = report.groups[1]
+            .files
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect();
+        assert_eq!(small_names, vec!["small_a.txt", "small_b.txt"]);
+        assert_eq!(report.skipped_counts.changed_during_scan, 1);
+        assert_eq!(report.skipped[0].reason, SkipReason::ChangedDuringScan);
+        assert!(report.skipped[0].path.ends_with("small_c.txt"));
+
+        assert_eq!(unchanged, vec![dir.join("small_b.txt")]);
+        let reasons: Vec<SkipReason> = confirm_skipped.iter().map(|s| s.reason).collect();
+        assert_eq!(
+            reasons,
+            vec![SkipReason::Vanished, SkipReason::ChangedDuringScan]
+        );
+    }
+
+    #[test]
+    fn test_partial_hash_prefilter() {
+        let test_dir = "test_partial_hash_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+
+        let size = (PARTIAL_HASH_FULL_LIMIT * 2) as usize;
+        let base = vec![b'a'; size];
+        // 先頭だけ異なる（部分ハッシュで除外される）
+        let mut head_differs = base.clone();
+        head_differs[0] = b'b';
+        // サンプル範囲外だけ異なる（部分ハッシュは衝突し、全体ハッシュで区別される）
+        let mut unsampled_differs = base.clone();
+        unsampled_differs[PARTIAL_HASH_BLOCK as usize + 1] = b'c';
+
+        let write = |name: &str, data: &[u8]| {
+            File::create(PathBuf::from(test_dir).join(name))
+                .unwrap()
+                .write_all(data)
+                .unwrap();
+        };
+        write("base.bin", &base);
+        write("base_copy.bin", &base);
+        write("head_differs.bin", &head_differs);
+        write("unsampled_differs.bin", &unsampled_differs);
+
+        let groups = scan(&ScanOptions {
+            paths: vec![test_dir.to_string()],
+            threads: Some(2),
+            ..Default::default()
+        });
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        let groups = groups.unwrap().groups;
+        assert_eq!(groups.len(), 1);
+        let names: Vec<&str> = groups[0].files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["base.bin", "base_copy.bin"]);
+    }
+
+    #[test]
+    fn test_groups_are_streamed_largest_first() {
+        let test_dir = "test_stream_groups_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+        // 全体ハッシュで確定するサイズと、部分ハッシュだけで確定するサイズを混ぜる
+        let sizes = [
+            10,
+            PARTIAL_HASH_FULL_LIMIT * 3,
+            100,
+            PARTIAL_HASH_FULL_LIMIT * 2,
+        ];
+        for (i, size) in sizes.iter().enumerate() {
+            let data = vec![b'a' + i as u8; *size as usize];
+            for copy in ["a", "b"] {
+                File::create(PathBuf::from(test_dir).join(format!("{}_{}.bin", i, copy)))
+                    .unwrap()
+                    .write_all(&data)
+                    .unwrap();
+            }
+        }
+
+        let streamed = Mutex::new(Vec::new());
+        let report = scan_for_duplicates(
+            &ScanOptions {
+                paths: vec![test_dir.to_string()],
+                threads: Some(4),
+                ..Default::default()
+            },
+            &ScanContext {
+                cache: None,
+                cancel: &AtomicBool::new(false),
+                on_progress: &|_| {},
+                on_group: &|group| streamed.lock().unwrap().push(group.size),
+            },
+        );
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        // 確定した順に大きいものから通知され、最終結果とも一致する
+        let streamed = streamed.into_inner().unwrap();
+        assert_eq!(
+            streamed,
+            vec![
+                PARTIAL_HASH_FULL_LIMIT * 3,
+                PARTIAL_HASH_FULL_LIMIT * 2,
+                100,
+                10
+            ]
+        );
+        let sizes: Vec<u64> = report.unwrap().groups.iter().map(|g| g.size).collect();
+        assert_eq!(sizes, streamed);
+    }
+
+    #[test]
+    fn test_hash_cache_is_reused() {
+        let test_dir = "test_scan_cache_dir";
+        let _ = fs::remove_dir_all(test_dir);
+        fs::create_dir(test_dir).unwrap();
+        let data = vec![b'z'; (PARTIAL_HASH_FULL_LIMIT * 2) as usize];
+        for name in ["a.bin", "b.bin"] {
+            File::create(PathBuf::from(test_dir).join(name))
+                .unwrap()
+                .write_all(&data)
+                .unwrap();
+        }
+
+        let cache = Mutex::new(HashCache::new(
+            PathBuf::from(test_dir).join("cache").join("hash_cache.bin"),
+        ));
+        let no_cancel = AtomicBool::new(false);
+        let first = scan_for_duplicates(
+            &ScanOptions {
+                paths: vec![test_dir.to_string()],
+                threads: Some(1),
+                ..Default::default()
+            },
+            &ScanContext {
+                cache: Some(&cache),
+                cancel: &no_cancel,
+                on_progress: &|_| {},
+                on_group: &|_| {},
+            },
+        );
+        let cached_entries = cache.lock().unwrap().info().entries;
+        let second = scan_for_duplicates(
+            &ScanOptions {
+                paths: vec![test_dir.to_string()],
+                threads: Some(1),
+                ..Default::default()
+            },
+            &ScanContext {
+                cache: Some(&cache),
+                cancel: &no_cancel,
+                on_progress: &|_| {},
+                on_group: &|_| {},
+            },
+        );
+
+        // 計算方法を変えると、キャッシュ済みの SHA-256 は使わずに計算し直す
+        let blake3 = scan_for_duplicates(
+            &ScanOptions {
+                paths: vec![test_dir.to_string()],
+                threads: Some(1),
+                hash_algorithm: HashAlgorithm::Blake3,
+                ..Default::default()
+            },
+            &ScanContext {
+                cache: Some(&cache),
+                cancel: &no_cancel,
+                on_progress: &|_| {},
+                on_group: &|_| {},
+            },
+        );
+
+        let _ = fs::remove_dir_all(test_dir);
+
+        assert_eq!(cached_entries, 2);
+        let (mut first, second) = (first.unwrap(), second.unwrap());
+        // 再利用した分は読み取ったバイト数に含めず、別に集計する
+        assert_eq!(first.stats.bytes_from_cache, 0);
+        assert_eq!(second.stats.bytes_from_cache, data.len() as u64 * 2);
+        assert_eq!(
+            second.stats.bytes_hashed + second.stats.bytes_from_cache,
+            first.stats.bytes_hashed
+        );
+        let first = first.groups.remove(0);
+        assert_eq!(first.hash, second.groups[0].hash);
+        assert_eq!(first.hash_algorithm, Some(HashAlgorithm::Sha256));
+        let blake3 = blake3.unwrap().groups.remove(0);
+        assert_eq!(blake3.hash_algorithm, Some(HashAlgorithm::Blake3));
+        assert_eq!(blake3.files.len(), 2);
+        assert_ne!(blake3.hash, first.hash);
+    }
+
+    /// テスト全体のアロケータを置き換えるため、`bench-memory` フィーチャーを指定したときだけ組み込む
+    #[cfg(feature = "bench-memory")]
+    mod memory_bench {
+        use super::*;
+
+        /// ヒープの使用量の最大値を記録するアロケータ（`bench_scan_peak_memory` 用）
+        struct PeakAllocator;
+
+        static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+        static PEAK_ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+        unsafe impl std::alloc::GlobalAlloc for PeakAllocator {
+            unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+                let ptr = std::alloc::System.alloc(layout);
+                if !ptr.is_null() {
+                    let now = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+                    PEAK_ALLOCATED.fetch_max(now, Ordering::Relaxed);
+                }
+                ptr
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+                std::alloc::System.dealloc(ptr, layout);
+                ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+            }
+        }
+
+        #[global_allocator]
+        static GLOBAL: PeakAllocator = PeakAllocator;
+
+        /// 大量のファイルをスキャンしたときのヒープ使用量の最大値を、100万ファイルあたりに換算して表示する
+        /// cargo test --release --features bench-memory bench_scan_peak_memory -- --ignored --nocapture
+        /// （ファイル数は環境変数 SCAN_BENCH_FILES で変更できる）
+        #[test]
+        #[ignore]
+        fn bench_scan_peak_memory() {
+            let file_count: usize = std::env::var("SCAN_BENCH_FILES")
+                .ok()
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(100_000);
+            let test_dir = "bench_scan_memory_dir";
+            let _ = fs::remove_dir_all(test_dir);
+            // 実際のアーカイブに近づけるため、1フォルダ100ファイルで3階層に分ける
+            for i in 0..file_count {
+                let dir = PathBuf::from(test_dir)
+                    .join(format!("year_{:04}", i / 10_000))
+                    .join(format!("album_{:04}", i / 100 % 100));
+                if i % 100 == 0 {
+                    fs::create_dir_all(&dir).unwrap();
+                }
+                // 内容は番号なので、同じ桁数のファイルがすべて同サイズの候補になる
+                fs::write(dir.join(format!("IMG_{:08}.jpg", i)), i.to_string()).unwrap();
+            }
+
+            let measure = |options: &ScanOptions| {
+                let baseline = ALLOCATED.load(Ordering::Relaxed);
+                PEAK_ALLOCATED.store(baseline, Ordering::Relaxed);
+                let started = Instant::now();
+                let report = scan(options).unwrap();
+                let peak = PEAK_ALLOCATED.load(Ordering::Relaxed) - baseline;
+                (report.stats.files_walked, peak, started.elapsed())
+            };
+            let print = |label: &str, (files, peak, elapsed): (u64, usize, Duration)| {
+                println!(
+                    "{}: {} ファイル, 最大 {:.1} MiB ({:.1} MiB / 100万ファイル), {:.2?}",
+                    label,
+                    files,
+                    peak as f64 / 1048576.0,
+                    peak as f64 / 1048576.0 * 1_000_000.0 / files.max(1) as f64,
+                    elapsed
+                );
+            };
+            // 走査とサイズによる絞り込みのみ（すべてサイズ範囲外）
+            let walk_only = measure(&ScanOptions {
+                paths: vec![test_dir.to_string()],
+                min_size: Some(u64::MAX),
+                ..Default::default()
+            });
+            // ほぼすべてのファイルが同サイズの候補になるが、内容はすべて異なる場合
+            let all_candidates = measure(&ScanOptions {
+                paths: vec![test_dir.to_string()],
+                ..Default::default()
+            });
+            // 候補がすべて1つのグループになる最悪の場合（報告するグループ自体の大きさを含む）
+            let all_grouped = measure(&ScanOptions {
+                paths: vec![test_dir.to_string()],
+                mode: ScanMode::SizeOnly,
+                ..Default::default()
+            });
+
+            let _ = fs::remove_dir_all(test_dir);
+
+            print("走査のみ", walk_only);
+            print("全ファイルが候補", all_candidates);
+            print("全ファイルが1グループ", all_grouped);
+        }
+    }
 }
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// 実行中のスキャンとそのキャンセルフラグ、削除を禁止する参照フォルダを管理する
+#[derive(Default)]
+pub struct ScanSessions {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, Arc<AtomicBool>>>,
+    /// スキャンごとの参照フォルダ（正規化済み）。結果の表示中は削除時に参照するため、終了後も保持する
+    reference_roots: Mutex<HashMap<u64, Vec<PathBuf>>>,
+}
+
+impl ScanSessions {
+    /// 新しいスキャンを登録し、IDとキャンセルフラグを返す
+    /// 画面の結果は新しいスキャンで置き換わるため、終了済みのスキャンの参照フォルダは破棄する
+    pub fn register(&self, reference_paths: &[String]) -> (u64, Arc<AtomicBool>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut active = self.active.lock().unwrap();
+        active.insert(id, cancel.clone());
+
+        let roots = reference_paths
+            .iter()
+            .map(|reference_path| {
+                let path = Path::new(reference_path);
+                fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+            })
+            .collect();
+        let mut reference_roots = self.reference_roots.lock().unwrap();
+        reference_roots.retain(|scan_id, _| active.contains_key(scan_id));
+        reference_roots.insert(id, roots);
+        (id, cancel)
+    }
+
+    /// 指定したスキャンにキャンセルを要求する（存在しなければ false）
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.active.lock().unwrap().get(&id) {
+            Some(cancel) => {
+                cancel.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 指定したスキャンの参照フォルダ（正規化済み）。その結果からの削除ではこの中のファイルを拒否する
+    /// 新しいスキャンで破棄された、または存在しないスキャンなら None
+    pub fn reference_roots(&self, id: u64) -> Option<Vec<PathBuf>> {
+        self.reference_roots.lock().unwrap().get(&id).cloned()
+    }
+
+    /// 終了したスキャンを登録から外す
+    pub fn finish(&self, id: u64) {
+        self.active.lock().unwrap().remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reference_roots_follow_latest_scan() {
+        let sessions = ScanSessions::default();
+        let (first, _) = sessions.register(&["/nonexistent/archive".to_string()]);
+        assert_eq!(
+            sessions.reference_roots(first),
+            Some(vec![PathBuf::from("/nonexistent/archive")])
+        );
+
+        // 実行中のスキャンの参照フォルダは、次のスキャンを始めても保持する
+        let (second, _) = sessions.register(&[]);
+        assert!(sessions.reference_roots(first).is_some());
+        sessions.finish(first);
+        sessions.finish(second);
+
+        // 以前のスキャンの参照フォルダは、新しいスキャンの結果からの削除を妨げない
+        let (third, _) = sessions.register(&[]);
+        assert_eq!(sessions.reference_roots(first), None);
+        assert_eq!(sessions.reference_roots(second), None);
+        assert_eq!(sessions.reference_roots(third), Some(vec![]));
+    }
+}
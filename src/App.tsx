@@ -1,5 +1,6 @@
-import { useState, useCallback, useEffect } from "react";
+import { useState, useCallback, useEffect, useRef } from "react";
 import { invoke, convertFileSrc } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { openPath } from "@tauri-apps/plugin-opener";
 import { open as openDialog, ask, message } from "@tauri-apps/plugin-dialog";
 import { check } from "@tauri-apps/plugin-updater";
@@ -13,12 +14,142 @@ interface FileInfo {
   size: number;
   hash: string;
   extension: string;
+  root: string;
+  is_reference: boolean;
+  symlinks: string[];
+  dev: number;
+  ino: number;
+  hardlinks: string[];
 }
 
 interface DuplicateGroup {
   hash: string;
   size: number;
   files: FileInfo[];
+  kind: "duplicate" | "empty_files" | "hardlinks";
+  hash_algorithm: HashAlgorithm | null;
+  verified: boolean;
+}
+
+type HashAlgorithm = "sha256" | "blake3" | "xxh3";
+type ScanMode = "strict" | "size_only" | "name_only";
+type NameMatch = "off" | "exact" | "ignore_case" | "ignore_copy_suffix";
+type StorageType = "auto" | "ssd" | "hdd";
+
+interface ScanOptions {
+  paths: string[];
+  reference_paths: string[];
+  mode: ScanMode;
+  name_match: NameMatch;
+  max_depth: number | null;
+  threads: number | null;
+  hash_algorithm: HashAlgorithm;
+  verify_contents: boolean;
+  include_globs: string[];
+  exclude_globs: string[];
+  extensions: string[];
+  excluded_extensions: string[];
+  respect_ignore_files: boolean;
+  follow_symlinks: boolean;
+  one_file_system: boolean;
+  excluded_fs_types: string[] | null;
+  skip_hidden: boolean;
+  system_excludes: string[] | null;
+  min_size: number | null;
+  max_size: number | null;
+  report_empty_files: boolean;
+  report_hardlinks: boolean;
+}
+
+// スキャン中に届いた重複グループを一覧に反映する間隔
+const GROUP_FLUSH_INTERVAL_MS = 100;
+
+// 開発用フォルダや OS が作るファイルなど、通常は重複として扱わないもの
+const DEFAULT_EXCLUDE_GLOBS = "node_modules, .git, Thumbs.db, .DS_Store, desktop.ini";
+
+// グループに表示するハッシュの計算方法の名前
+const HASH_ALGORITHM_LABELS: Record<HashAlgorithm, string> = {
+  sha256: "SHA-256",
+  blake3: "BLAKE3",
+  xxh3: "xxh3"
+};
+
+// カンマ区切りの入力を配列にする
+function splitList(value: string): string[] {
+  return value.split(",").map((v) => v.trim()).filter((v) => v.length > 0);
+}
+
+// KB 単位の入力をバイト数にする（空欄は制限なし）
+function parseSizeKb(value: string): number | null {
+  const kb = parseFloat(value);
+  return Number.isFinite(kb) && kb >= 0 ? Math.round(kb * 1024) : null;
+}
+
+interface ScanProgress {
+  scan_id: number;
+  phase: "collecting" | "grouping" | "prefiltering" | "hashing" | "done";
+  files_discovered: number;
+  files_hashed: number;
+  files_to_hash: number;
+  bytes_hashed: number;
+  bytes_to_hash: number;
+  files_skipped: number;
+  current_path: string | null;
+}
+
+interface SkippedPath {
+  path: string;
+  reason: "permission_denied" | "vanished" | "io_error" | "changed_during_scan";
+  message: string;
+}
+
+interface ScanStats {
+  files_walked: number;
+  dirs_walked: number;
+  bytes_examined: number;
+  bytes_hashed: number;
+  bytes_from_cache: number;
+  groups: number;
+  redundant_copies: number;
+  reclaimable_bytes: number;
+}
+
+interface ScanReport {
+  groups: DuplicateGroup[];
+  skipped: SkippedPath[];
+  skipped_counts: {
+    permission_denied: number;
+    vanished: number;
+    io_error: number;
+    changed_during_scan: number;
+  };
+  stats: ScanStats;
+  timings: {
+    collecting_ms: number;
+    grouping_ms: number;
+    prefiltering_ms: number;
+    hashing_ms: number;
+    total_ms: number;
+  };
+  options: ScanOptions;
+}
+
+interface ScanGroup {
+  scan_id: number;
+  group: DuplicateGroup;
+}
+
+interface ScanFinished {
+  scan_id: number;
+  report: ScanReport | null;
+  error: string | null;
+  cancelled: boolean;
+}
+
+interface HashCacheInfo {
+  path: string;
+  entries: number;
+  file_size: number;
 }
 
 interface FilePreview {
@@ -39,18 +170,69 @@ function formatSize(bytes: number): string {
   return `${(bytes / Math.pow(1024, i)).toFixed(i > 0 ? 1 : 0)} ${units[i]}`;
 }
 
+// 1つを残して削除した場合に空く容量
+// ハードリンクは1ファイルにまとめられているため、実体が1つだけのグループは 0 になる
+function reclaimableSize(group: DuplicateGroup): number {
+  if (group.kind !== "duplicate") return 0;
+  const deletable = group.files.filter((f) => !f.is_reference).map((f) => f.size);
+  const total = deletable.reduce((sum, size) => sum + size, 0);
+  // 参照フォルダにコピーがあれば、参照以外はすべて削除できる
+  if (group.files.some((f) => f.is_reference)) return total;
+  return deletable.length > 1 ? total - Math.max(...deletable) : 0;
+}
+
+function describeProgress(progress: ScanProgress | null): string {
+  if (!progress) return "ファイルをスキャンしています...";
+  switch (progress.phase) {
+    case "collecting":
+      return `ファイルを列挙中... (${progress.files_discovered}件)`;
+    case "grouping":
+      return `サイズで分類中... (${progress.files_discovered}件)`;
+    case "prefiltering":
+      return `部分ハッシュで絞り込み中... ${progress.files_hashed} / ${progress.files_to_hash}件`;
+    case "hashing":
+      return `ハッシュ計算中... ${progress.files_hashed} / ${progress.files_to_hash}件 (${formatSize(progress.bytes_hashed)} / ${formatSize(progress.bytes_to_hash)})`;
+    case "done":
+      return "結果を集計中...";
+  }
+}
+
 function App() {
-  const [folderPath, setFolderPath] = useState<string>("");
+  const [folderPaths, setFolderPaths] = useState<string[]>([]);
+  const [referencePaths, setReferencePaths] = useState<string[]>([]);
   const [groups, setGroups] = useState<DuplicateGroup[]>([]);
+  const [skipped, setSkipped] = useState<SkippedPath[]>([]);
+  const [scanStats, setScanStats] = useState<ScanReport["stats"] | null>(null);
   const [selectedFiles, setSelectedFiles] = useState<Set<string>>(new Set());
   const [preview, setPreview] = useState<FilePreview | null>(null);
   const [isScanning, setIsScanning] = useState(false);
+  const [isDeleting, setIsDeleting] = useState(false);
+  const [scanProgress, setScanProgress] = useState<ScanProgress | null>(null);
+  const scanIdRef = useRef<number | null>(null);
+  // 表示中の結果のスキャンID（削除時にそのスキャンの参照フォルダを保護するために渡す）
+  const [resultScanId, setResultScanId] = useState<number | null>(null);
   const [showConfirm, setShowConfirm] = useState(false);
   const [toast, setToast] = useState<string | null>(null);
   const [scanComplete, setScanComplete] = useState(false);
   const [appVersion, setAppVersion] = useState("");
-  const [scanMode, setScanMode] = useState<"strict" | "size_only">("strict");
-  const [recursive, setRecursive] = useState(false);
+  const [scanMode, setScanMode] = useState<ScanMode>("strict");
+  const [nameMatch, setNameMatch] = useState<NameMatch>("off");
+  // 走査するサブフォルダの深さ（"all" は無制限）
+  const [maxDepth, setMaxDepth] = useState("0");
+  const [respectIgnoreFiles, setRespectIgnoreFiles] = useState(false);
+  const [followSymlinks, setFollowSymlinks] = useState(false);
+  const [oneFileSystem, setOneFileSystem] = useState(false);
+  const [skipHidden, setSkipHidden] = useState(true);
+  const [skipSystemFolders, setSkipSystemFolders] = useState(true);
+  const [excludeGlobs, setExcludeGlobs] = useState(DEFAULT_EXCLUDE_GLOBS);
+  const [extensions, setExtensions] = useState("");
+  const [minSizeKb, setMinSizeKb] = useState("");
+  const [maxSizeKb, setMaxSizeKb] = useState("");
+  const [reportEmptyFiles, setReportEmptyFiles] = useState(false);
+  const [reportHardlinks, setReportHardlinks] = useState(false);
+  const [storageType, setStorageType] = useState<StorageType>("auto");
+  const [hashAlgorithm, setHashAlgorithm] = useState<HashAlgorithm>("sha256");
+  const [verifyContents, setVerifyContents] = useState(false);
 
   // 初期化時にバージョン取得
   useEffect(() => {
@@ -91,17 +273,48 @@ function App() {
     }
   };
 
+  // ハッシュキャッシュの確認・削除
+  const manageHashCache = async () => {
+    try {
+      const info = await invoke<HashCacheInfo>("get_hash_cache_info");
+      const yes = await ask(
+        `保存済みハッシュ: ${info.entries}件 (${formatSize(info.file_size)})\n${info.path}\n\nキャッシュを削除しますか？`,
+        { title: 'ハッシュキャッシュ', kind: 'info' }
+      );
+      if (yes) {
+        await invoke("clear_hash_cache");
+        showToast("ハッシュキャッシュを削除しました");
+      }
+    } catch (e) {
+      showToast(`キャッシュ操作エラー: ${e}`);
+    }
+  };
+
   // トースト表示
   const showToast = useCallback((msg: string) => {
     setToast(msg);
     setTimeout(() => setToast(null), 3000);
   }, []);
 
-  // フォルダ選択
+  // フォルダ選択（選択済みのフォルダに追加する）
   const pickFolder = async () => {
-    const selected = await openDialog({ directory: true, multiple: false });
+    const selected = await openDialog({ directory: true, multiple: true });
+    if (selected) {
+      const picked = Array.isArray(selected) ? selected : [selected];
+      setFolderPaths((prev) => Array.from(new Set([...prev, ...picked])));
+      setScanComplete(false);
+      setGroups([]);
+      setSelectedFiles(new Set());
+      setPreview(null);
+    }
+  };
+
+  // 参照フォルダ選択（比較のみに使い、中のファイルは削除しない）
+  const pickReferenceFolder = async () => {
+    const selected = await openDialog({ directory: true, multiple: true });
     if (selected) {
-      setFolderPath(selected as string);
+      const picked = Array.isArray(selected) ? selected : [selected];
+      setReferencePaths((prev) => Array.from(new Set([...prev, ...picked])));
       setScanComplete(false);
       setGroups([]);
       setSelectedFiles(new Set());
@@ -109,29 +322,144 @@ function App() {
     }
   };
 
+  // 選択フォルダのクリア
+  const clearFolders = () => {
+    setFolderPaths([]);
+    setReferencePaths([]);
+    setScanComplete(false);
+    setGroups([]);
+    setSelectedFiles(new Set());
+    setPreview(null);
+  };
+
   // スキャン実行
   const startScan = async () => {
-    if (!folderPath) return;
+    if (folderPaths.length === 0) return;
     setIsScanning(true);
     setScanComplete(false);
     setGroups([]);
+    setSkipped([]);
+    setScanStats(null);
     setSelectedFiles(new Set());
     setPreview(null);
+    setScanProgress(null);
+    setResultScanId(null);
+    scanIdRef.current = null;
+
+    // start_scan の戻り値より先にイベントが届く場合に備えて保持しておく
+    const earlyGroups: ScanGroup[] = [];
+    const earlyFinished: ScanFinished[] = [];
+    let resolveFinished: (finished: ScanFinished) => void = () => {};
+    const finishedPromise = new Promise<ScanFinished>((resolve) => {
+      resolveFinished = resolve;
+    });
+    const unlistenProgress = await listen<ScanProgress>("scan-progress", (event) => {
+      if (event.payload.scan_id === scanIdRef.current) {
+        setScanProgress(event.payload);
+      }
+    });
+    // 確定した重複グループはスキャン中から大きい順に表示する
+    // 小さいファイルのグループは最後にまとめて届くため、一件ずつ配列を作り直さず一定間隔でまとめて追加する
+    let pendingGroups: DuplicateGroup[] = [];
+    const flushGroups = () => {
+      if (pendingGroups.length === 0) return;
+      const batch = pendingGroups;
+      pendingGroups = [];
+      setGroups((prev) => prev.concat(batch));
+    };
+    const unlistenGroup = await listen<ScanGroup>("scan-group", (event) => {
+      if (scanIdRef.current === null) {
+        earlyGroups.push(event.payload);
+      } else if (event.payload.scan_id === scanIdRef.current) {
+        pendingGroups.push(event.payload.group);
+      }
+    });
+    const unlistenFinished = await listen<ScanFinished>("scan-finished", (event) => {
+      if (scanIdRef.current === null) {
+        earlyFinished.push(event.payload);
+      } else if (event.payload.scan_id === scanIdRef.current) {
+        resolveFinished(event.payload);
+      }
+    });
+    const flushTimer = window.setInterval(flushGroups, GROUP_FLUSH_INTERVAL_MS);
     try {
-      const result = await invoke<DuplicateGroup[]>("scan_folder", {
-        path: folderPath,
+      const options: ScanOptions = {
+        paths: folderPaths,
+        reference_paths: referencePaths,
         mode: scanMode,
-        recursive: recursive
-      });
-      setGroups(result);
-      setScanComplete(true);
-      if (result.length === 0) {
-        showToast("重複ファイルは見つかりませんでした");
+        // ファイル名のみのモードでは名前の比較が必須
+        name_match: scanMode === "name_only" && nameMatch === "off" ? "exact" : nameMatch,
+        max_depth: maxDepth === "all" ? null : Number(maxDepth),
+        // 自動ではバックエンドが HDD を検出して1スレッドにする（並列読み取りでシークが増えて遅くなるため）
+        threads: storageType === "hdd" ? 1 : storageType === "ssd" ? Math.min(navigator.hardwareConcurrency || 1, 8) : null,
+        hash_algorithm: hashAlgorithm,
+        // バイト単位の検証は厳密モードのみ
+        verify_contents: scanMode === "strict" && verifyContents,
+        include_globs: [],
+        exclude_globs: splitList(excludeGlobs),
+        extensions: splitList(extensions),
+        excluded_extensions: [],
+        respect_ignore_files: respectIgnoreFiles,
+        follow_symlinks: followSymlinks,
+        one_file_system: oneFileSystem,
+        // null なら proc や sshfs などの組み込みの一覧を走査しない
+        excluded_fs_types: null,
+        skip_hidden: skipHidden,
+        // null なら組み込みの一覧（~/.cache やゴミ箱など）を除外する
+        system_excludes: skipSystemFolders ? null : [],
+        min_size: parseSizeKb(minSizeKb),
+        max_size: parseSizeKb(maxSizeKb),
+        report_empty_files: reportEmptyFiles,
+        report_hardlinks: reportHardlinks
+      };
+      const scanId = await invoke<number>("start_scan", { options });
+      scanIdRef.current = scanId;
+      setResultScanId(scanId);
+      const streamed = earlyGroups.filter((g) => g.scan_id === scanId).map((g) => g.group);
+      if (streamed.length > 0) setGroups((prev) => [...streamed, ...prev]);
+      const early = earlyFinished.find((f) => f.scan_id === scanId);
+      if (early) resolveFinished(early);
+
+      const finished = await finishedPromise;
+      if (finished.cancelled) {
+        showToast("スキャンをキャンセルしました");
+      } else if (finished.error !== null) {
+        showToast(`エラー: ${finished.error}`);
+      } else {
+        // 別枠のグループを含めて並べ直した最終結果で置き換える
+        pendingGroups = [];
+        const report = finished.report;
+        const result = report?.groups ?? [];
+        setGroups(result);
+        setSkipped(report?.skipped ?? []);
+        setScanStats(report?.stats ?? null);
+        setScanComplete(true);
+        if (result.length === 0) {
+          showToast("重複ファイルは見つかりませんでした");
+        }
       }
     } catch (e) {
       showToast(`エラー: ${e}`);
     } finally {
+      unlistenProgress();
+      unlistenGroup();
+      unlistenFinished();
+      // キャンセル・エラー時はそれまでに届いたグループを表示したままにする
+      window.clearInterval(flushTimer);
+      flushGroups();
+      scanIdRef.current = null;
       setIsScanning(false);
+      setScanProgress(null);
+    }
+  };
+
+  // スキャン中断
+  const cancelScan = async () => {
+    if (scanIdRef.current === null) return;
+    try {
+      await invoke("cancel_scan", { scanId: scanIdRef.current });
+    } catch (e) {
+      showToast(`キャンセルエラー: ${e}`);
     }
   };
 
@@ -151,14 +479,24 @@ function App() {
   // 全選択 / 全解除 / 一つを残して選択
   const selectAll = () => {
     const allPaths = new Set<string>();
-    groups.forEach((g) => g.files.forEach((f) => allPaths.add(f.path)));
+    groups.forEach((g) => g.files.forEach((f) => {
+      if (!f.is_reference && g.kind !== "hardlinks") allPaths.add(f.path);
+    }));
     setSelectedFiles(allPaths);
   };
 
   const selectAllButOne = () => {
     const pathsToSelect = new Set<string>();
     groups.forEach((group) => {
-      if (group.files.length > 1) {
+      if (group.kind === "hardlinks") {
+        // 実体が1つしかないため削除しても容量は空かない
+        return;
+      } else if (group.kind === "empty_files" || group.files.some((f) => f.is_reference)) {
+        // 空ファイルと、参照フォルダにコピーがあるグループは、参照以外をすべて選択する
+        group.files.forEach((file) => {
+          if (!file.is_reference) pathsToSelect.add(file.path);
+        });
+      } else if (group.files.length > 1) {
         // Find the file with the maximum size
         let maxSizeFileIndex = 0;
         let maxSize = group.files[0].size;
@@ -184,6 +522,19 @@ function App() {
     setSelectedFiles(new Set());
   };
 
+  // 読み取れない・スキャン中に変更されたなどで比較から外したファイルの一覧を表示
+  const showSkipped = async () => {
+    const reasons = {
+      permission_denied: "アクセス権なし",
+      vanished: "スキャン中に削除",
+      io_error: "読み取りエラー",
+      changed_during_scan: "スキャン中に変更"
+    };
+    const shown = skipped.slice(0, 30).map((s) => `[${reasons[s.reason]}] ${s.path}`);
+    if (skipped.length > shown.length) shown.push(`...他 ${skipped.length - shown.length}件`);
+    await message(shown.join("\n"), { title: `比較から外したファイル (${skipped.length}件)`, kind: "warning" });
+  };
+
   // プレビュー取得
   const loadPreview = async (path: string) => {
     try {
@@ -201,21 +552,28 @@ function App() {
   // 削除実行
   const executeDelete = async () => {
     setShowConfirm(false);
-    const paths = Array.from(selectedFiles);
+    if (resultScanId === null) return;
+    // ハードリンクは1つのファイルとして扱い、同じ実体を共有するパスもまとめて削除する
+    const hardlinksOf = new Map<string, string[]>();
+    groups.forEach((g) => g.files.forEach((f) => hardlinksOf.set(f.path, f.hardlinks)));
+    const paths = Array.from(selectedFiles).flatMap((p) => [p, ...(hardlinksOf.get(p) ?? [])]);
+    // 削除はバックグラウンドで行われ、その間もプレビューなどの操作はできる
+    setIsDeleting(true);
     try {
-      const result = await invoke<DeleteResult>("delete_files", { paths });
+      const result = await invoke<DeleteResult>("delete_files", { scanId: resultScanId, paths });
       const deletedCount = result.deleted.length;
       const failedCount = result.failed.length;
 
       // 削除済みファイルをリストから除去
       const deletedSet = new Set(result.deleted);
-      const updatedGroups = groups
-        .map((g) => ({
-          ...g,
-          files: g.files.filter((f) => !deletedSet.has(f.path)),
-        }))
-        .filter((g) => g.files.length >= 2);
-      setGroups(updatedGroups);
+      setGroups((prev) =>
+        prev
+          .map((g) => ({
+            ...g,
+            files: g.files.filter((f) => !deletedSet.has(f.path)),
+          }))
+          .filter((g) => g.files.length >= (g.kind === "duplicate" ? 2 : 1))
+      );
       setSelectedFiles(new Set());
       setPreview(null);
 
@@ -226,10 +584,16 @@ function App() {
       }
     } catch (e) {
       showToast(`削除エラー: ${e}`);
+    } finally {
+      setIsDeleting(false);
     }
   };
 
-  const totalDuplicateFiles = groups.reduce((sum, g) => sum + g.files.length, 0);
+  const totalDuplicateFiles = groups.reduce(
+    (sum, g) => sum + g.files.filter((f) => !f.is_reference).length,
+    0
+  );
+  const totalReclaimable = groups.reduce((sum, g) => sum + reclaimableSize(g), 0);
 
   return (
     <div className="app">
@@ -243,14 +607,34 @@ function App() {
           <button className="btn btn-ghost" onClick={checkForUpdates} style={{ fontSize: "11px", padding: "4px 8px" }}>
             🔄 更新を確認
           </button>
+          <button className="btn btn-ghost" onClick={manageHashCache} disabled={isScanning} style={{ fontSize: "11px", padding: "4px 8px" }}>
+            🗃️ キャッシュ
+          </button>
           {scanComplete && groups.length > 0 && (
-            <span className="status-badge warning">
-              {groups.length}グループ・{totalDuplicateFiles}ファイル
+            <span
+              className="status-badge warning"
+              title={
+                scanStats
+                  ? `${scanStats.files_walked}ファイル・${scanStats.dirs_walked}フォルダを走査 (${formatSize(scanStats.bytes_examined)}、うち${formatSize(scanStats.bytes_hashed)}を読み取り、${formatSize(scanStats.bytes_from_cache)}はキャッシュを再利用)`
+                  : undefined
+              }
+            >
+              {groups.length}グループ・{totalDuplicateFiles}ファイル・削減可能 {formatSize(totalReclaimable)}
             </span>
           )}
           {scanComplete && groups.length === 0 && (
             <span className="status-badge success">✓ 重複なし</span>
           )}
+          {scanComplete && skipped.length > 0 && (
+            <span
+              className="status-badge warning"
+              style={{ cursor: "pointer" }}
+              onClick={showSkipped}
+              title="読み取れなかったファイルやスキャン中に変更されたファイルは比較していないため、見つかっていない重複がある可能性があります"
+            >
+              ⚠ {skipped.length}件を比較から除外
+            </span>
+          )}
         </div>
       </header>
 
@@ -258,37 +642,91 @@ function App() {
       <div className="folder-picker">
         <div className="picker-container" style={{ display: "flex", gap: "10px", width: "100%", alignItems: "center" }}>
           <button className="btn btn-ghost" onClick={pickFolder} style={{ flexShrink: 0 }}>
-            📁 フォルダ選択
+            📁 フォルダ追加
           </button>
-          <div className="folder-path" style={{ flexGrow: 1, textOverflow: "ellipsis", overflow: "hidden", whiteSpace: "nowrap" }}>
-            {folderPath || "スキャンするフォルダを選択してください..."}
+          <div className="folder-path" title={folderPaths.join("\n")} style={{ flexGrow: 1, textOverflow: "ellipsis", overflow: "hidden", whiteSpace: "nowrap" }}>
+            {folderPaths.length > 0 ? folderPaths.join(" ; ") : "スキャンするフォルダを選択してください..."}
+            {referencePaths.length > 0 && ` （参照: ${referencePaths.join(" ; ")}）`}
           </div>
+          <button className="btn btn-ghost" onClick={pickReferenceFolder} disabled={isScanning} title="比較にのみ使い、中のファイルは削除しないフォルダ" style={{ flexShrink: 0 }}>
+            📚 参照フォルダ
+          </button>
+          {(folderPaths.length > 0 || referencePaths.length > 0) && (
+            <button className="btn btn-ghost" onClick={clearFolders} disabled={isScanning} style={{ flexShrink: 0 }}>
+              ✕
+            </button>
+          )}
 
           <select
             className="select-input"
             value={scanMode}
-            onChange={(e) => setScanMode(e.target.value as "strict" | "size_only")}
+            onChange={(e) => setScanMode(e.target.value as ScanMode)}
             title="検出モード"
             style={{ padding: "8px", borderRadius: "4px", border: "1px solid var(--border-color)", background: "var(--bg-secondary)", color: "var(--text-primary)" }}
           >
             <option value="strict">完全一致 (推奨)</option>
             <option value="size_only">サイズのみ比較 (高速)</option>
+            <option value="name_only">ファイル名のみ比較</option>
           </select>
 
-          <label style={{ display: "flex", alignItems: "center", gap: "5px", cursor: "pointer", fontSize: "14px", flexShrink: 0, paddingRight: "8px" }}>
-            <input
-              type="checkbox"
-              checked={recursive}
-              onChange={(e) => setRecursive(e.target.checked)}
-              disabled={isScanning}
-            />
-            サブフォルダも検索する
-          </label>
+          <select
+            className="select-input"
+            value={nameMatch}
+            onChange={(e) => setNameMatch(e.target.value as NameMatch)}
+            title="ファイル名の比較"
+            disabled={isScanning}
+            style={{ padding: "8px", borderRadius: "4px", border: "1px solid var(--border-color)", background: "var(--bg-secondary)", color: "var(--text-primary)" }}
+          >
+            <option value="off">{scanMode === "name_only" ? "名前: 完全一致" : "名前: 比較しない"}</option>
+            <option value="exact">名前: 完全一致</option>
+            <option value="ignore_case">名前: 大文字小文字を無視</option>
+            <option value="ignore_copy_suffix">名前: 「(1)」「- コピー」等を無視</option>
+          </select>
+
+          <select
+            className="select-input"
+            value={storageType}
+            onChange={(e) => setStorageType(e.target.value as StorageType)}
+            title="ストレージの種類"
+            disabled={isScanning}
+            style={{ padding: "8px", borderRadius: "4px", border: "1px solid var(--border-color)", background: "var(--bg-secondary)", color: "var(--text-primary)" }}
+          >
+            <option value="auto">ストレージ: 自動判定</option>
+            <option value="ssd">SSD (並列読み取り)</option>
+            <option value="hdd">HDD (逐次読み取り)</option>
+          </select>
+
+          <select
+            className="select-input"
+            value={hashAlgorithm}
+            onChange={(e) => setHashAlgorithm(e.target.value as HashAlgorithm)}
+            title="ハッシュの計算方法（厳密モード）"
+            disabled={isScanning || scanMode !== "strict"}
+            style={{ padding: "8px", borderRadius: "4px", border: "1px solid var(--border-color)", background: "var(--bg-secondary)", color: "var(--text-primary)" }}
+          >
+            <option value="sha256">SHA-256</option>
+            <option value="blake3">BLAKE3 (高速)</option>
+          </select>
+
+          <select
+            className="select-input"
+            value={maxDepth}
+            onChange={(e) => setMaxDepth(e.target.value)}
+            title="サブフォルダの検索"
+            disabled={isScanning}
+            style={{ padding: "8px", borderRadius: "4px", border: "1px solid var(--border-color)", background: "var(--bg-secondary)", color: "var(--text-primary)" }}
+          >
+            <option value="0">このフォルダのみ</option>
+            <option value="1">1階層下まで</option>
+            <option value="2">2階層下まで</option>
+            <option value="3">3階層下まで</option>
+            <option value="all">すべてのサブフォルダ</option>
+          </select>
 
           <button
             className="btn btn-primary"
             onClick={startScan}
-            disabled={!folderPath || isScanning}
+            disabled={folderPaths.length === 0 || isScanning || isDeleting}
             style={{ flexShrink: 0 }}
           >
             {isScanning ? "⏳ スキャン中..." : "🔍 スキャン"}
@@ -296,6 +734,135 @@ function App() {
         </div>
       </div>
 
+      {/* Filters */}
+      <div className="folder-picker">
+        <div className="picker-container" style={{ display: "flex", gap: "10px", width: "100%", alignItems: "center", fontSize: "13px" }}>
+          <label style={{ flexShrink: 0 }}>除外パターン</label>
+          <input
+            type="text"
+            value={excludeGlobs}
+            onChange={(e) => setExcludeGlobs(e.target.value)}
+            disabled={isScanning}
+            placeholder="例: node_modules, *.tmp, photos/cache"
+            title="カンマ区切り。「/」を含むパターンはフォルダからの相対パスに一致"
+            style={{ flexGrow: 2, padding: "6px", borderRadius: "4px", border: "1px solid var(--border-color)", background: "var(--bg-secondary)", color: "var(--text-primary)" }}
+          />
+          <label style={{ flexShrink: 0 }}>拡張子</label>
+          <input
+            type="text"
+            value={extensions}
+            onChange={(e) => setExtensions(e.target.value)}
+            disabled={isScanning}
+            placeholder="すべて (例: jpg, png)"
+            style={{ flexGrow: 1, padding: "6px", borderRadius: "4px", border: "1px solid var(--border-color)", background: "var(--bg-secondary)", color: "var(--text-primary)" }}
+          />
+          <label style={{ flexShrink: 0 }}>サイズ (KB)</label>
+          <input
+            type="number"
+            min="0"
+            value={minSizeKb}
+            onChange={(e) => setMinSizeKb(e.target.value)}
+            disabled={isScanning}
+            placeholder="最小"
+            style={{ width: "70px", padding: "6px", borderRadius: "4px", border: "1px solid var(--border-color)", background: "var(--bg-secondary)", color: "var(--text-primary)" }}
+          />
+          <span>〜</span>
+          <input
+            type="number"
+            min="0"
+            value={maxSizeKb}
+            onChange={(e) => setMaxSizeKb(e.target.value)}
+            disabled={isScanning}
+            placeholder="最大"
+            style={{ width: "70px", padding: "6px", borderRadius: "4px", border: "1px solid var(--border-color)", background: "var(--bg-secondary)", color: "var(--text-primary)" }}
+          />
+          <label style={{ display: "flex", alignItems: "center", gap: "4px", cursor: "pointer", flexShrink: 0 }}>
+            <input
+              type="checkbox"
+              checked={reportEmptyFiles}
+              onChange={(e) => setReportEmptyFiles(e.target.checked)}
+              disabled={isScanning}
+            />
+            空ファイルを別枠で表示
+          </label>
+          <label style={{ display: "flex", alignItems: "center", gap: "4px", cursor: "pointer", flexShrink: 0 }}>
+            <input
+              type="checkbox"
+              checked={reportHardlinks}
+              onChange={(e) => setReportHardlinks(e.target.checked)}
+              disabled={isScanning}
+            />
+            ハードリンクを別枠で表示
+          </label>
+          <label
+            style={{ display: "flex", alignItems: "center", gap: "4px", cursor: "pointer", flexShrink: 0 }}
+            title="ハッシュが一致したファイルをさらにバイト単位で比較する（遅いが確実）"
+          >
+            <input
+              type="checkbox"
+              checked={verifyContents}
+              onChange={(e) => setVerifyContents(e.target.checked)}
+              disabled={isScanning || scanMode !== "strict"}
+            />
+            バイト単位で検証
+          </label>
+          <label
+            style={{ display: "flex", alignItems: "center", gap: "4px", cursor: "pointer", flexShrink: 0 }}
+            title=".gitignore（Git リポジトリ内のみ）・.ignore・.dupignore に書かれたファイルとフォルダを除外"
+          >
+            <input
+              type="checkbox"
+              checked={respectIgnoreFiles}
+              onChange={(e) => setRespectIgnoreFiles(e.target.checked)}
+              disabled={isScanning}
+            />
+            .gitignore に従う
+          </label>
+          <label style={{ display: "flex", alignItems: "center", gap: "4px", cursor: "pointer", flexShrink: 0 }}>
+            <input
+              type="checkbox"
+              checked={skipHidden}
+              onChange={(e) => setSkipHidden(e.target.checked)}
+              disabled={isScanning}
+            />
+            隠しファイルを除外
+          </label>
+          <label style={{ display: "flex", alignItems: "center", gap: "4px", cursor: "pointer", flexShrink: 0 }}>
+            <input
+              type="checkbox"
+              checked={followSymlinks}
+              onChange={(e) => setFollowSymlinks(e.target.checked)}
+              disabled={isScanning}
+            />
+            シンボリックリンクを辿る
+          </label>
+          <label
+            style={{ display: "flex", alignItems: "center", gap: "4px", cursor: "pointer", flexShrink: 0 }}
+            title="マウントされたドライブやネットワーク共有など、別のファイルシステムに入らない"
+          >
+            <input
+              type="checkbox"
+              checked={oneFileSystem}
+              onChange={(e) => setOneFileSystem(e.target.checked)}
+              disabled={isScanning}
+            />
+            同じファイルシステム内のみ
+          </label>
+          <label
+            style={{ display: "flex", alignItems: "center", gap: "4px", cursor: "pointer", flexShrink: 0 }}
+            title="~/.cache やゴミ箱など、キャッシュ・システム用のフォルダを走査しない"
+          >
+            <input
+              type="checkbox"
+              checked={skipSystemFolders}
+              onChange={(e) => setSkipSystemFolders(e.target.checked)}
+              disabled={isScanning}
+            />
+            キャッシュ・ゴミ箱を除外
+          </label>
+        </div>
+      </div>
+
       {/* Main Content */}
       <div className="main-content">
         {/* Duplicate List */}
@@ -303,7 +870,15 @@ function App() {
           {isScanning && (
             <div className="loading">
               <div className="spinner" />
-              <div className="loading-text">ファイルをスキャンしています...</div>
+              <div className="loading-text">{describeProgress(scanProgress)}</div>
+              {scanProgress?.current_path && (
+                <div className="loading-text" style={{ fontSize: "11px", color: "var(--text-muted)", wordBreak: "break-all" }}>
+                  {scanProgress.current_path}
+                </div>
+              )}
+              <button className="btn btn-ghost" onClick={cancelScan}>
+                ⏹ キャンセル
+              </button>
             </div>
           )}
 
@@ -333,12 +908,19 @@ function App() {
                 <div className="group-info">
                   <span className="group-badge">{group.files.length}件</span>
                   <span className="group-size">
-                    各 {formatSize(group.size)}
+                    {group.kind === "empty_files"
+                      ? "空ファイル"
+                      : group.kind === "hardlinks"
+                        ? `ハードリンク (${formatSize(group.size)})`
+                        : `各 ${formatSize(group.size)}`}
                   </span>
                 </div>
-                <span className="group-size" title={group.hash}>
-                  SHA-256: {group.hash.substring(0, 12)}...
-                </span>
+                {group.hash_algorithm !== null && (
+                  <span className="group-size" title={group.hash}>
+                    {HASH_ALGORITHM_LABELS[group.hash_algorithm]}: {group.hash.substring(0, 12)}...
+                    {group.verified && " ✔ バイト単位で一致"}
+                  </span>
+                )}
               </div>
               {group.files.map((file) => (
                 <div
@@ -349,6 +931,14 @@ function App() {
                   <input
                     type="checkbox"
                     className="file-checkbox"
+                    disabled={file.is_reference || group.kind === "hardlinks"}
+                    title={
+                      file.is_reference
+                        ? "参照フォルダのファイルは削除できません"
+                        : group.kind === "hardlinks"
+                          ? "ハードリンクは容量を消費しないため重複ではありません"
+                          : undefined
+                    }
                     checked={selectedFiles.has(file.path)}
                     onChange={(e) => {
                       e.stopPropagation();
@@ -357,8 +947,21 @@ function App() {
                     onClick={(e) => e.stopPropagation()}
                   />
                   <div className="file-info">
-                    <div className="file-name">{file.name}</div>
+                    <div className="file-name">
+                      {file.is_reference && <span className="status-badge success" style={{ marginRight: "6px" }}>参照</span>}
+                      {file.name}
+                    </div>
                     <div className="file-path">{file.path}</div>
+                    {file.hardlinks.length > 0 && (
+                      <div className="file-path" title="同じ実体を共有するハードリンク（まとめて削除されます）">
+                        ⛓ {file.hardlinks.join(", ")}
+                      </div>
+                    )}
+                    {file.symlinks.length > 0 && (
+                      <div className="file-path" title="削除するとこれらのリンクはリンク切れになります">
+                        🔗 {file.symlinks.join(", ")}
+                      </div>
+                    )}
                   </div>
                   <span className="file-size-tag">{formatSize(file.size)}</span>
                 </div>
@@ -430,10 +1033,10 @@ function App() {
             </button>
             <button
               className="btn btn-danger"
-              disabled={selectedFiles.size === 0}
+              disabled={selectedFiles.size === 0 || isScanning || isDeleting}
               onClick={() => setShowConfirm(true)}
             >
-              🗑️ 選択ファイルを削除
+              {isDeleting ? "⏳ 削除中..." : "🗑️ 選択ファイルを削除"}
             </button>
           </div>
         </div>